@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::mesh::Mesh;
+use crate::{mesh_comments, Terrain};
+
+/// Which side of the cropping height to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Above,
+    Below,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum VertexKey {
+    Grid(usize, usize),
+    Crossing((usize, usize), (usize, usize)),
+}
+
+/// The portion of the surface of a terrain that survives cropping, made of polygons that index
+/// into `vertices`.
+#[derive(Debug)]
+pub struct Region {
+    pub vertices: Vec<(f32, f32, f32)>,
+    pub polygons: Vec<Vec<usize>>,
+}
+
+/// Build a solid that only covers the region of the terrain that is both on the given `side` of
+/// `sea_level`, if any, and inside the footprint mask of the terrain.
+pub fn cropped_solid(terrain: &Terrain, sea_level: Option<f32>, side: Side) -> Mesh {
+    let mut mesh = Mesh::new(mesh_comments(terrain));
+    mesh.append(crop(terrain, sea_level, side).solid(None, terrain.floor()));
+    mesh
+}
+
+/// Find the region of the terrain that is both on the given `side` of `sea_level`, if any, and
+/// inside the footprint mask of the terrain.
+///
+/// The outline is found via marching squares where each cell is split along its diagonal to avoid
+/// ambiguous saddles.
+pub fn crop(terrain: &Terrain, sea_level: Option<f32>, side: Side) -> Region {
+    let wet = |(x, y): (usize, usize)| match sea_level {
+        None => true,
+        Some(sea_level) => (terrain.height_at(x, y) > sea_level) == (side == Side::Above),
+    };
+    let masked = |(x, y): (usize, usize)| terrain.mask_at(x, y) > 0.0;
+    let inside = |p: (usize, usize)| wet(p) && masked(p);
+
+    // the crossing closest to the inside endpoint is where the cropped region ends, note that
+    // both the sea level and the mask might cross the same edge.
+    let crossing = |a: (usize, usize), b: (usize, usize)| {
+        let mut ts = vec![];
+
+        if wet(a) != wet(b) {
+            let h0 = terrain.height_at(a.0, a.1);
+            let h1 = terrain.height_at(b.0, b.1);
+            ts.push((sea_level.unwrap_or(0.0) - h0) / (h1 - h0));
+        }
+        if masked(a) != masked(b) {
+            let m0 = terrain.mask_at(a.0, a.1);
+            let m1 = terrain.mask_at(b.0, b.1);
+            ts.push(m0 / (m0 - m1));
+        }
+
+        if inside(a) {
+            ts.into_iter().fold(1.0, f32::min)
+        } else {
+            ts.into_iter().fold(0.0, f32::max)
+        }
+    };
+
+    let mut vertices = vec![];
+    let mut ids = HashMap::new();
+    let mut vertex_id = |key: VertexKey| {
+        *ids.entry(key).or_insert_with(|| {
+            let v = match key {
+                VertexKey::Grid(x, y) => (x as f32, y as f32, terrain.height_at(x, y)),
+                VertexKey::Crossing((x0, y0), (x1, y1)) => {
+                    let h0 = terrain.height_at(x0, y0);
+                    let h1 = terrain.height_at(x1, y1);
+                    let t = crossing((x0, y0), (x1, y1));
+
+                    (
+                        x0 as f32 + (x1 as f32 - x0 as f32) * t,
+                        y0 as f32 + (y1 as f32 - y0 as f32) * t,
+                        h0 + (h1 - h0) * t,
+                    )
+                }
+            };
+            vertices.push(v);
+            vertices.len() - 1
+        })
+    };
+
+    let mut polygons = vec![];
+    for y in 0..terrain.depth().saturating_sub(1) {
+        for x in 0..terrain.width().saturating_sub(1) {
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+
+            if corners.iter().all(|&c| inside(c)) {
+                polygons.push(
+                    corners
+                        .iter()
+                        .map(|&(x, y)| vertex_id(VertexKey::Grid(x, y)))
+                        .collect::<Vec<_>>(),
+                );
+                continue;
+            }
+
+            for tri in &[
+                [corners[0], corners[1], corners[2]],
+                [corners[0], corners[2], corners[3]],
+            ] {
+                let mut poly = vec![];
+
+                for i in 0..tri.len() {
+                    let a = tri[i];
+                    let b = tri[(i + 1) % tri.len()];
+
+                    if inside(a) {
+                        poly.push(vertex_id(VertexKey::Grid(a.0, a.1)));
+                    }
+
+                    if inside(a) != inside(b) {
+                        let (a, b) = if a < b { (a, b) } else { (b, a) };
+                        poly.push(vertex_id(VertexKey::Crossing(a, b)));
+                    }
+                }
+
+                if poly.len() >= 3 {
+                    polygons.push(poly);
+                }
+            }
+        }
+    }
+
+    Region { vertices, polygons }
+}
+
+impl Region {
+    /// Build the region as a solid with vertical walls along its outline that go down to
+    /// `bottom`. The top is either the surface of the terrain or the flat plane at `top`, if
+    /// any.
+    pub fn solid(&self, top: Option<f32>, bottom: f32) -> Mesh {
+        let mut edges_count = HashMap::new();
+        for poly in &self.polygons {
+            for (a, b) in polygon_edges(poly) {
+                *edges_count.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        let mut mesh = Mesh::default();
+        for &(x, y, z) in &self.vertices {
+            mesh.add_vertex([x, y, top.unwrap_or(z)]);
+        }
+        for &(x, y, _) in &self.vertices {
+            mesh.add_vertex([x, y, bottom]);
+        }
+
+        let oi = self.vertices.len();
+        for poly in &self.polygons {
+            mesh.add_face(poly.clone(), None);
+            mesh.add_face(poly.iter().rev().map(|i| oi + i).collect(), None);
+        }
+
+        for poly in &self.polygons {
+            for (a, b) in polygon_edges(poly) {
+                if edges_count[&(a.min(b), a.max(b))] != 1 {
+                    continue;
+                }
+
+                mesh.add_face(vec![oi + a, oi + b, b, a], None);
+            }
+        }
+
+        mesh
+    }
+}
+
+fn polygon_edges(poly: &[usize]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    poly.iter()
+        .zip(poly.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
@@ -0,0 +1,91 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::Noise;
+
+/// An additional layer of noise that is combined with the terrain, like the detail on top of the
+/// mountains on top of the continents. Written as `NOISE` or `NOISE:key=value,...` where the keys
+/// are `frequency`, `amplitude`, `octaves`, `gain`, `lacunarity`, `seed` and `op`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseLayer {
+    pub noise: Noise,
+    pub frequency: Option<f32>,
+    pub octaves: Option<u8>,
+    pub gain: Option<f32>,
+    pub lacunarity: Option<f32>,
+    pub seed: Option<u64>,
+
+    /// The weight of the layer, whose values are in [0, 1] before being combined.
+    pub amplitude: f32,
+
+    pub op: Op,
+}
+
+/// How a layer is combined with the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Op {
+    Add,
+    Multiply,
+    Min,
+    Max,
+}
+
+impl Op {
+    pub fn apply(self, below: f32, layer: f32) -> f32 {
+        match self {
+            Op::Add => below + layer,
+            Op::Multiply => below * layer,
+            Op::Min => below.min(layer),
+            Op::Max => below.max(layer),
+        }
+    }
+}
+
+impl FromStr for NoiseLayer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (noise, params) = match s.split_once(':') {
+            Some((noise, params)) => (noise, params),
+            None => (s, ""),
+        };
+
+        let mut layer = NoiseLayer {
+            noise: Noise::from_str(noise.trim(), true)?,
+            frequency: None,
+            octaves: None,
+            gain: None,
+            lacunarity: None,
+            seed: None,
+            amplitude: 1.0,
+            op: Op::Add,
+        };
+
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value but got `{}`", param))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "frequency" => layer.frequency = Some(parse_value(key, value)?),
+                "octaves" => layer.octaves = Some(parse_value(key, value)?),
+                "gain" => layer.gain = Some(parse_value(key, value)?),
+                "lacunarity" => layer.lacunarity = Some(parse_value(key, value)?),
+                "seed" => layer.seed = Some(parse_value(key, value)?),
+                "amplitude" => layer.amplitude = parse_value(key, value)?,
+                "op" => layer.op = Op::from_str(value, true)?,
+                _ => return Err(format!("unknown layer parameter `{}`", key)),
+            }
+        }
+
+        Ok(layer)
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid value for {}", value, key))
+}
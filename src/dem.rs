@@ -0,0 +1,385 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::Parser;
+
+/// Meters per degree of latitude, and of longitude at the equator.
+pub const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Parser)]
+pub struct DemConfig {
+    /// Input SRTM .hgt tiles, adjacent tiles are stitched together. The names of the files must
+    /// follow the SRTM convention, like `N46E007.hgt`, because they're used to georeference the
+    /// tiles. Voids are left out of the footprint. Other formats, like GeoTIFF, aren't supported,
+    /// use `heightmap --geotiff` for them.
+    #[arg(required = true)]
+    pub tiles: Vec<PathBuf>,
+
+    /// The region to turn into a mesh as `min_lon,min_lat,max_lon,max_lat` in degrees. Defaults
+    /// to the area covered by the tiles.
+    #[arg(long, allow_hyphen_values = true)]
+    pub bbox: Option<GeoBbox>,
+
+    /// The distance in meters between two adjacent vertices of the final mesh. Defaults to the
+    /// resolution of the tile.
+    #[arg(short, long)]
+    pub resolution: Option<f32>,
+
+    /// How much to exaggerate the elevation compared to the horizontal scale.
+    #[arg(short, long, default_value = "1.0")]
+    pub exaggeration: f32,
+
+    /// The thickness of the base upon which the terrain is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    pub base_thickness: f32,
+}
+
+/// A bounding box in geographic coordinates, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoBbox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// How the terrain maps to the real world. The terrain is projected onto a local grid centered
+/// at the center of `bbox` using an equirectangular projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoReference {
+    pub bbox: GeoBbox,
+    pub center_lon: f64,
+    pub center_lat: f64,
+
+    /// The distance in meters between two adjacent vertices.
+    pub cell_size: f32,
+
+    pub exaggeration: f32,
+
+    /// The elevation in meters that corresponds to the top of the base.
+    pub min_elevation: f32,
+}
+
+/// A single SRTM tile of `size` x `size` big endian samples ordered from north to south and from
+/// west to east.
+#[derive(Debug)]
+pub struct HgtTile {
+    pub lon: f64,
+    pub lat: f64,
+    pub size: usize,
+    pub samples: Vec<i16>,
+}
+
+/// Adjacent SRTM tiles of the same resolution stitched together.
+#[derive(Debug)]
+pub struct HgtMosaic {
+    tiles: Vec<HgtTile>,
+}
+
+/// The value SRTM uses to mark missing samples.
+pub const HGT_VOID: i16 = -32768;
+
+impl FromStr for GeoBbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid coordinate: {}", e))?;
+
+        match coords[..] {
+            [min_lon, min_lat, max_lon, max_lat] if min_lon < max_lon && min_lat < max_lat => {
+                Ok(GeoBbox {
+                    min_lon,
+                    min_lat,
+                    max_lon,
+                    max_lat,
+                })
+            }
+            _ => Err("expected min_lon,min_lat,max_lon,max_lat with min < max".to_string()),
+        }
+    }
+}
+
+impl GeoBbox {
+    /// Whether the point is inside the bbox, or at most `tolerance` degrees outside of it.
+    pub fn contains_point(&self, lon: f64, lat: f64, tolerance: f64) -> bool {
+        self.min_lon - tolerance <= lon
+            && self.min_lat - tolerance <= lat
+            && lon <= self.max_lon + tolerance
+            && lat <= self.max_lat + tolerance
+    }
+
+    pub fn union(&self, other: &GeoBbox) -> GeoBbox {
+        GeoBbox {
+            min_lon: self.min_lon.min(other.min_lon),
+            min_lat: self.min_lat.min(other.min_lat),
+            max_lon: self.max_lon.max(other.max_lon),
+            max_lat: self.max_lat.max(other.max_lat),
+        }
+    }
+}
+
+impl HgtTile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_uppercase())
+            .unwrap_or_default();
+        let (lat, lon) = parse_hgt_name(&name)
+            .ok_or_else(|| invalid(format!("{} is not a valid SRTM tile name", name)))?;
+
+        let data = fs::read(path)?;
+        let size = ((data.len() / 2) as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != data.len() {
+            return Err(invalid(format!(
+                "{} is not a square grid of 16 bit samples",
+                path.display()
+            )));
+        }
+
+        let samples = data
+            .chunks_exact(2)
+            .map(|c| i16::from_be_bytes([c[0], c[1]]))
+            .collect();
+
+        Ok(HgtTile {
+            lon,
+            lat,
+            size,
+            samples,
+        })
+    }
+
+    pub fn bbox(&self) -> GeoBbox {
+        GeoBbox {
+            min_lon: self.lon,
+            min_lat: self.lat,
+            max_lon: self.lon + 1.0,
+            max_lat: self.lat + 1.0,
+        }
+    }
+
+    /// The distance in degrees between two adjacent samples.
+    pub fn spacing(&self) -> f64 {
+        1.0 / (self.size - 1) as f64
+    }
+
+    /// Bilinearly interpolate the elevation at the given coordinates from the samples that aren't
+    /// voids, `None` if there are none.
+    pub fn elevation_at(&self, lon: f64, lat: f64) -> Option<f32> {
+        let n = (self.size - 1) as f64;
+        let col = ((lon - self.lon) * n).max(0.0).min(n);
+        let row = ((self.lat + 1.0 - lat) * n).max(0.0).min(n);
+
+        let c0 = col.floor() as usize;
+        let r0 = row.floor() as usize;
+        let c1 = (c0 + 1).min(self.size - 1);
+        let r1 = (r0 + 1).min(self.size - 1);
+        let tc = (col - c0 as f64) as f32;
+        let tr = (row - r0 as f64) as f32;
+
+        let corners = [
+            (r0, c0, (1.0 - tr) * (1.0 - tc)),
+            (r0, c1, (1.0 - tr) * tc),
+            (r1, c0, tr * (1.0 - tc)),
+            (r1, c1, tr * tc),
+        ];
+
+        // voids are left out and the weights of the other samples renormalized, so that they
+        // don't drag the elevation down to sea level
+        let (sum, total) = corners
+            .iter()
+            .filter(|&&(r, c, _)| self.samples[r * self.size + c] != HGT_VOID)
+            .fold((0.0, 0.0), |(sum, total), &(r, c, w)| {
+                (
+                    sum + f32::from(self.samples[r * self.size + c]) * w,
+                    total + w,
+                )
+            });
+
+        if total > f32::EPSILON {
+            Some(sum / total)
+        } else {
+            None
+        }
+    }
+}
+
+impl HgtMosaic {
+    /// Open all the given tiles, which must have the same resolution and cover different areas.
+    pub fn open(paths: &[PathBuf]) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let mut tiles: Vec<HgtTile> = Vec::with_capacity(paths.len());
+        for path in paths {
+            let tile = HgtTile::open(path)?;
+
+            if let Some(first) = tiles.first() {
+                if first.size != tile.size {
+                    return Err(invalid(format!(
+                        "{} has a different resolution than the other tiles",
+                        path.display()
+                    )));
+                }
+            }
+
+            if tiles.iter().any(|t| t.bbox() == tile.bbox()) {
+                return Err(invalid(format!(
+                    "{} covers the same area as another tile",
+                    path.display()
+                )));
+            }
+
+            tiles.push(tile);
+        }
+
+        if tiles.is_empty() {
+            return Err(invalid("no tiles given".to_string()));
+        }
+
+        Ok(HgtMosaic { tiles })
+    }
+
+    /// The bbox that contains all the tiles, note that it might not be completely covered by
+    /// them.
+    pub fn bbox(&self) -> GeoBbox {
+        self.tiles[1..]
+            .iter()
+            .fold(self.tiles[0].bbox(), |bbox, t| bbox.union(&t.bbox()))
+    }
+
+    /// The distance in degrees between two adjacent samples.
+    pub fn spacing(&self) -> f64 {
+        self.tiles[0].spacing()
+    }
+
+    /// Whether every point of the bbox is covered by a tile.
+    pub fn covers(&self, bbox: &GeoBbox) -> bool {
+        // tiles are 1 degree wide and aligned to whole degrees
+        let lons = bbox.min_lon.floor() as i32..bbox.max_lon.ceil() as i32;
+        let lats = bbox.min_lat.floor() as i32..bbox.max_lat.ceil() as i32;
+
+        lats.flat_map(|lat| lons.clone().map(move |lon| (lon, lat)))
+            .all(|(lon, lat)| {
+                self.tiles
+                    .iter()
+                    .any(|t| t.lon == f64::from(lon) && t.lat == f64::from(lat))
+            })
+    }
+
+    /// Interpolate the elevation at the given coordinates using the tile that contains them,
+    /// `None` for voids and points outside of all the tiles.
+    pub fn elevation_at(&self, lon: f64, lat: f64) -> Option<f32> {
+        // adjacent tiles share their border samples so it doesn't matter which one is picked on
+        // the edges, the tolerance only absorbs rounding errors of the projection
+        let tolerance = self.spacing() / 2.0;
+
+        self.tiles
+            .iter()
+            .find(|t| t.bbox().contains_point(lon, lat, 0.0))
+            .or_else(|| {
+                self.tiles
+                    .iter()
+                    .find(|t| t.bbox().contains_point(lon, lat, tolerance))
+            })
+            .and_then(|t| t.elevation_at(lon, lat))
+    }
+}
+
+impl GeoReference {
+    /// Project the given grid position back to geographic coordinates.
+    pub fn lon_lat_at(&self, x: f64, y: f64) -> (f64, f64) {
+        let meters_per_lon = METERS_PER_DEGREE * self.center_lat.to_radians().cos();
+
+        (
+            self.bbox.min_lon + x * f64::from(self.cell_size) / meters_per_lon,
+            self.bbox.min_lat + y * f64::from(self.cell_size) / METERS_PER_DEGREE,
+        )
+    }
+
+    /// Project the given geographic coordinates onto the grid, the inverse of `lon_lat_at`.
+    pub fn grid_at(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let meters_per_lon = METERS_PER_DEGREE * self.center_lat.to_radians().cos();
+
+        (
+            (lon - self.bbox.min_lon) * meters_per_lon / f64::from(self.cell_size),
+            (lat - self.bbox.min_lat) * METERS_PER_DEGREE / f64::from(self.cell_size),
+        )
+    }
+
+    /// The size in meters of the bbox once projected on the local grid.
+    pub fn extent(bbox: &GeoBbox) -> (f64, f64) {
+        let center_lat = (bbox.min_lat + bbox.max_lat) / 2.0;
+
+        (
+            (bbox.max_lon - bbox.min_lon) * METERS_PER_DEGREE * center_lat.to_radians().cos(),
+            (bbox.max_lat - bbox.min_lat) * METERS_PER_DEGREE,
+        )
+    }
+
+    /// Model units per meter of elevation.
+    pub fn vertical_scale(&self) -> f32 {
+        self.exaggeration / self.cell_size
+    }
+
+    /// Write the georeferencing info as a JSON sidecar.
+    pub fn dump_sidecar(&self, w: &mut impl Write, width: usize, depth: usize) -> io::Result<()> {
+        writeln!(
+            w,
+            r#"{{
+  "bbox": [{}, {}, {}, {}],
+  "center": [{}, {}],
+  "projection": "equirectangular",
+  "width": {},
+  "depth": {},
+  "cell_size": {},
+  "exaggeration": {},
+  "min_elevation": {},
+  "vertical_scale": {}
+}}"#,
+            self.bbox.min_lon,
+            self.bbox.min_lat,
+            self.bbox.max_lon,
+            self.bbox.max_lat,
+            self.center_lon,
+            self.center_lat,
+            width,
+            depth,
+            self.cell_size,
+            self.exaggeration,
+            self.min_elevation,
+            self.vertical_scale(),
+        )
+    }
+}
+
+/// Parse the coordinates of the south west corner of a tile from a name like `N46E007`.
+fn parse_hgt_name(name: &str) -> Option<(f64, f64)> {
+    if name.len() != 7 || !name.is_ascii() {
+        return None;
+    }
+
+    let lat = name[1..3].parse::<f64>().ok()?;
+    let lon = name[4..7].parse::<f64>().ok()?;
+
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => return None,
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => return None,
+    };
+
+    Some((lat, lon))
+}
@@ -0,0 +1,152 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+
+use clap::Parser;
+
+use crate::grid::{blur, normalize};
+
+/// The slope, in slabs per cell, below which the lee side of a dune shelters the sand from the
+/// wind, about 15 degrees with slabs a third of a cell tall.
+const SHADOW_SLOPE: f32 = 0.8;
+
+/// The steepest difference in slabs between two adjacent cells before the sand avalanches, that
+/// is the angle of repose.
+const REPOSE: i32 = 2;
+
+/// The farthest distance upwind that can cast a shadow.
+const MAX_SHADOW: usize = 20;
+
+#[derive(Parser)]
+pub struct DunesConfig {
+    /// The width of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub width: u16,
+
+    /// The depth of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub depth: u16,
+
+    /// The seed to use to generate the terrain. You can find the seed of a given terrain by
+    /// inspecting the obj file.
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// The direction the wind blows towards in degrees, counter clockwise from the x axis.
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    pub wind: f32,
+
+    /// The average thickness of the sand in slabs. Scarce sand produces isolated barchan dunes
+    /// while abundant sand produces transverse ridges.
+    #[arg(long, default_value = "3.0")]
+    pub sand: f32,
+
+    /// How far in cells a grain of sand jumps at every hop.
+    #[arg(long, default_value = "3")]
+    pub hop: u16,
+
+    /// The number of simulated events per cell.
+    #[arg(long, default_value = "200")]
+    pub iterations: usize,
+
+    /// The maximum height of the terrain. If `base-thickness` is specified then the final mesh has
+    /// a potential maximum height of `base-thickness` + `amplitude`.
+    #[arg(short, long, default_value = "10")]
+    pub amplitude: f32,
+
+    /// The thickness of the base upon which the terrain is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    pub base_thickness: f32,
+}
+
+/// Simulate the transport of sand by the wind and return the resulting heights normalized in
+/// [0, 1] in row major order.
+///
+/// This is the slab model by Werner: sand is made of discrete slabs that the wind picks up from
+/// random cells and moves downwind in hops until they're deposited, more likely on sand than on
+/// bare ground and always in the shadow of a dune. Slopes steeper than the angle of repose
+/// avalanche. The domain wraps around so that the sand never leaves it.
+pub fn simulate(cfg: &DunesConfig, seed: u64) -> Vec<f32> {
+    let width = usize::from(cfg.width);
+    let depth = usize::from(cfg.depth);
+    let mut rng = Pcg32::seed_from_u64(seed);
+
+    let mut slabs = (0..width * depth)
+        .map(|_| (cfg.sand.max(0.0) * 2.0 * rng.gen::<f32>()).round() as i32)
+        .collect::<Vec<_>>();
+
+    let angle = cfg.wind.to_radians();
+    let wind = (angle.cos(), angle.sin());
+    let hop = f32::from(cfg.hop.max(1));
+
+    let wrap = |x: f32, y: f32| {
+        let x = (x.round() as isize).rem_euclid(width as isize) as usize;
+        let y = (y.round() as isize).rem_euclid(depth as isize) as usize;
+        y * width + x
+    };
+
+    let in_shadow = |slabs: &[i32], i: usize| {
+        let (x, y) = ((i % width) as f32, (i / width) as f32);
+
+        (1..=MAX_SHADOW).any(|d| {
+            let d = d as f32;
+            let j = wrap(x - wind.0 * d, y - wind.1 * d);
+            (slabs[j] - slabs[i]) as f32 > d * SHADOW_SLOPE
+        })
+    };
+
+    for _ in 0..cfg.iterations * width * depth {
+        let i = rng.gen_range(0..width * depth);
+        if slabs[i] == 0 || in_shadow(&slabs, i) {
+            continue;
+        }
+
+        slabs[i] -= 1;
+        avalanche(&mut slabs, width, depth, i);
+
+        let (mut x, mut y) = ((i % width) as f32, (i / width) as f32);
+        loop {
+            x += wind.0 * hop;
+            y += wind.1 * hop;
+            let j = wrap(x, y);
+
+            let p = if slabs[j] > 0 { 0.6 } else { 0.4 };
+            if in_shadow(&slabs, j) || rng.gen_bool(p) {
+                slabs[j] += 1;
+                avalanche(&mut slabs, width, depth, j);
+                break;
+            }
+        }
+    }
+
+    let heights = slabs.iter().map(|&s| s as f32).collect::<Vec<_>>();
+    let mut heights = blur(heights, width, depth, 0.7);
+    normalize(&mut heights);
+    heights
+}
+
+/// Restore the angle of repose around the cell `i` after its height changed, moving slabs
+/// downhill until all the slopes are stable again.
+fn avalanche(slabs: &mut [i32], width: usize, depth: usize, i: usize) {
+    let neighbors = |i: usize| {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+
+        [(-1, 0), (1, 0), (0, -1), (0, 1)].map(|(dx, dy)| {
+            let nx = (x + dx).rem_euclid(width as isize) as usize;
+            let ny = (y + dy).rem_euclid(depth as isize) as usize;
+            ny * width + nx
+        })
+    };
+
+    let mut unstable = vec![i];
+    while let Some(c) = unstable.pop() {
+        for n in neighbors(c) {
+            let (hi, lo) = if slabs[n] > slabs[c] { (n, c) } else { (c, n) };
+
+            if slabs[hi] - slabs[lo] > REPOSE {
+                slabs[hi] -= 1;
+                slabs[lo] += 1;
+                unstable.extend_from_slice(&[hi, lo]);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::grid::Raster;
+
+/// How the elevation is stored in the pixels of a heightmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// The brightness of the pixel is the height.
+    Grayscale,
+
+    /// Terrarium tiles, like the ones of the AWS terrain tiles, where the elevation in meters is
+    /// `R * 256 + G + B / 256 - 32768`.
+    Terrarium,
+
+    /// Mapbox Terrain-RGB tiles, where the elevation in meters is
+    /// `-10000 + (R * 65536 + G * 256 + B) * 0.1`.
+    MapboxRgb,
+}
+
+impl Encoding {
+    /// The elevation in meters of an RGB pixel, `Grayscale` doesn't encode elevations.
+    fn elevation(self, [r, g, b]: [u8; 3]) -> Option<f32> {
+        let (r, g, b) = (f64::from(r), f64::from(g), f64::from(b));
+
+        match self {
+            Encoding::Grayscale => None,
+            Encoding::Terrarium => Some((r * 256.0 + g + b / 256.0 - 32768.0) as f32),
+            Encoding::MapboxRgb => Some((-10_000.0 + (r * 65536.0 + g * 256.0 + b) * 0.1) as f32),
+        }
+    }
+}
+
+/// Decode the elevations of an RGB encoded image, the transparent pixels are missing.
+pub fn read_encoded(path: &Path, encoding: Encoding) -> image::ImageResult<Raster> {
+    let img = image::open(path)?.to_rgba8();
+
+    let samples = img
+        .pixels()
+        .map(|p| {
+            let [r, g, b, a] = p.0;
+            if a == 0 {
+                None
+            } else {
+                encoding.elevation([r, g, b])
+            }
+        })
+        .collect();
+
+    Ok(Raster {
+        width: img.width() as usize,
+        depth: img.height() as usize,
+        samples,
+    })
+}
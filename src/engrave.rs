@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::io;
+
+use clap::Parser;
+
+use crate::Terrain;
+
+#[derive(Parser)]
+pub struct EngraveConfig {
+    /// Engrave a scale bar onto the front wall of the base. The length of the bar is written in
+    /// the header of the obj file. Requires `cell-size` unless the terrain is georeferenced.
+    #[arg(long = "scale-bar", conflicts_with_all = ["sea_level", "mask"])]
+    pub scale_bar: bool,
+
+    /// Engrave an arrow pointing north, that is towards the back of the terrain, onto the front
+    /// wall of the base.
+    #[arg(long = "north-arrow", conflicts_with_all = ["sea_level", "mask"])]
+    pub north_arrow: bool,
+
+    /// The real world distance in meters between two adjacent vertices of the terrain. Defaults to
+    /// the resolution of georeferenced terrains.
+    #[arg(long = "cell-size")]
+    pub cell_size: Option<f32>,
+
+    /// How deep the engravings are.
+    #[arg(long = "engrave-depth", default_value = "0.5")]
+    pub depth: f32,
+}
+
+/// A vertex of the engraved front wall, either one of the vertices the terrain already has or an
+/// extra one the engraving needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WallVertex {
+    Top(usize),
+    Floor(usize),
+    Extra(usize),
+}
+
+/// The faces that replace the front wall of the base.
+#[derive(Debug)]
+pub struct Engraving {
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<Vec<WallVertex>>,
+
+    /// The vertices of the left (x = 0) and right (x = width - 1) borders of the wall from the
+    /// bottom up, excluding the floor, that the side walls must share.
+    pub left_border: Vec<WallVertex>,
+    pub right_border: Vec<WallVertex>,
+
+    /// The real world length of the scale bar in meters, if any.
+    pub scale_bar_length: Option<f32>,
+}
+
+const ARROW: [&str; 7] = [
+    "..#..", //
+    ".###.", //
+    "#####", //
+    "..#..", //
+    "..#..", //
+    "..#..", //
+    "..#..", //
+];
+
+impl EngraveConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.scale_bar || self.north_arrow
+    }
+}
+
+/// Carve the configured engravings into the front wall (y = 0) of the base of the terrain.
+///
+/// The engravings are a set of rectangular recesses, the wall is split in a grid along all the
+/// edges of the rectangles so that all the faces share their vertices. The solid stays watertight
+/// only if the side walls are split along the vertices of `left_border` and `right_border` too.
+pub fn engrave_front_wall(terrain: &Terrain, cfg: &EngraveConfig) -> io::Result<Engraving> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+
+    let width = terrain.width();
+    if width < 2 || terrain.depth() < 2 {
+        return Err(invalid("the terrain is too small to be engraved"));
+    }
+
+    // the recesses must not reach the back wall, nor come close to it
+    if !cfg.depth.is_finite() || cfg.depth <= 0.0 || cfg.depth >= (terrain.depth() - 1) as f32 / 2.0
+    {
+        return Err(invalid(
+            "engrave-depth must be positive and less than half the depth of the terrain",
+        ));
+    }
+
+    let floor = terrain.floor();
+    let wall_top = (0..width)
+        .map(|x| terrain.height_at(x, 0))
+        .fold(f32::INFINITY, f32::min);
+
+    let band_top = wall_top - 0.5;
+    let band_height = band_top - floor;
+    if band_height <= 0.0 {
+        return Err(invalid(
+            "not enough room on the front wall to engrave, try to increase base-thickness",
+        ));
+    }
+
+    let usable_height = band_height * 0.6;
+    let mid = floor + band_height / 2.0;
+    let right_edge = (width - 1) as f32;
+
+    let mut holes = vec![];
+    let mut cursor = right_edge * 0.05;
+    let mut scale_bar_length = None;
+
+    if cfg.scale_bar {
+        let cell_size = cfg
+            .cell_size
+            .or_else(|| terrain.cell_size())
+            .ok_or_else(|| invalid("the scale bar requires cell-size"))?;
+        let length = nice_length(right_edge * cell_size * 0.4);
+        let bar_width = length / cell_size;
+        let bar_height = usable_height * 0.3;
+        let tick_width = (bar_width * 0.03).max(0.1);
+
+        holes.push([cursor, cursor + bar_width, mid - bar_height / 2.0, mid]);
+        holes.push([cursor, cursor + tick_width, mid - usable_height / 2.0, mid]);
+        holes.push([
+            cursor + bar_width - tick_width,
+            cursor + bar_width,
+            mid - usable_height / 2.0,
+            mid,
+        ]);
+
+        cursor += bar_width + right_edge * 0.05;
+        scale_bar_length = Some(length);
+    }
+
+    if cfg.north_arrow {
+        let s = usable_height / ARROW.len() as f32;
+        let top = mid + usable_height / 2.0;
+
+        for (r, row) in ARROW.iter().enumerate() {
+            for (c, _) in row.chars().enumerate().filter(|(_, ch)| *ch == '#') {
+                let x0 = cursor + c as f32 * s;
+                let z1 = top - r as f32 * s;
+                holes.push([x0, x0 + s, z1 - s, z1]);
+            }
+        }
+
+        cursor += ARROW[0].len() as f32 * s;
+    }
+
+    if cursor >= right_edge {
+        return Err(invalid("the front wall is too narrow for the engravings"));
+    }
+
+    let mut xs = (0..width).map(|x| x as f32).collect::<Vec<_>>();
+    let mut zs = vec![floor, band_top];
+    for &[x0, x1, z0, z1] in &holes {
+        xs.extend_from_slice(&[x0, x1]);
+        zs.extend_from_slice(&[z0, z1]);
+    }
+    for v in [&mut xs, &mut zs] {
+        v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        v.dedup();
+    }
+
+    let is_hole = |i: usize, j: usize| {
+        let cx = (xs[i] + xs[i + 1]) / 2.0;
+        let cz = (zs[j] + zs[j + 1]) / 2.0;
+
+        holes
+            .iter()
+            .any(|&[x0, x1, z0, z1]| x0 <= cx && cx <= x1 && z0 <= cz && cz <= z1)
+    };
+
+    let mut vertices = vec![];
+    let mut ids = HashMap::new();
+    let mut vertex = |i: usize, j: usize, back: bool| {
+        let x = xs[i];
+        let z = zs[j];
+
+        if !back && j == 0 && x.fract() == 0.0 {
+            return WallVertex::Floor(x as usize);
+        }
+
+        *ids.entry((i, j, back)).or_insert_with(|| {
+            vertices.push([x, if back { cfg.depth } else { 0.0 }, z]);
+            WallVertex::Extra(vertices.len() - 1)
+        })
+    };
+
+    let mut faces = vec![];
+    for i in 0..xs.len() - 1 {
+        for j in 0..zs.len() - 1 {
+            if !is_hole(i, j) {
+                faces.push(vec![
+                    vertex(i, j, false),
+                    vertex(i + 1, j, false),
+                    vertex(i + 1, j + 1, false),
+                    vertex(i, j + 1, false),
+                ]);
+                continue;
+            }
+
+            faces.push(vec![
+                vertex(i, j, true),
+                vertex(i + 1, j, true),
+                vertex(i + 1, j + 1, true),
+                vertex(i, j + 1, true),
+            ]);
+
+            if !is_hole(i, j - 1) {
+                faces.push(vec![
+                    vertex(i, j, false),
+                    vertex(i + 1, j, false),
+                    vertex(i + 1, j, true),
+                    vertex(i, j, true),
+                ]);
+            }
+            if !is_hole(i, j + 1) {
+                faces.push(vec![
+                    vertex(i + 1, j + 1, false),
+                    vertex(i, j + 1, false),
+                    vertex(i, j + 1, true),
+                    vertex(i + 1, j + 1, true),
+                ]);
+            }
+            if !is_hole(i - 1, j) {
+                faces.push(vec![
+                    vertex(i, j + 1, false),
+                    vertex(i, j, false),
+                    vertex(i, j, true),
+                    vertex(i, j + 1, true),
+                ]);
+            }
+            if !is_hole(i + 1, j) {
+                faces.push(vec![
+                    vertex(i + 1, j, false),
+                    vertex(i + 1, j + 1, false),
+                    vertex(i + 1, j + 1, true),
+                    vertex(i + 1, j, true),
+                ]);
+            }
+        }
+    }
+
+    let band_top_row = zs.len() - 1;
+    for x in 0..width - 1 {
+        let mut face = xs
+            .iter()
+            .enumerate()
+            .filter(|(_, &bx)| x as f32 <= bx && bx <= (x + 1) as f32)
+            .map(|(i, _)| vertex(i, band_top_row, false))
+            .collect::<Vec<_>>();
+
+        face.push(WallVertex::Top(x + 1));
+        face.push(WallVertex::Top(x));
+        faces.push(face);
+    }
+
+    let last = xs.len() - 1;
+    let left_border = (1..zs.len()).map(|j| vertex(0, j, false)).collect();
+    let right_border = (1..zs.len()).map(|j| vertex(last, j, false)).collect();
+
+    Ok(Engraving {
+        vertices,
+        faces,
+        left_border,
+        right_border,
+        scale_bar_length,
+    })
+}
+
+/// Find the largest length of the form {1, 2, 5} * 10^k that is not greater than `max_length`.
+fn nice_length(max_length: f32) -> f32 {
+    let magnitude = 10_f32.powf(max_length.log10().floor());
+
+    [5.0, 2.0, 1.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&l| l <= max_length)
+        .unwrap_or(magnitude)
+}
@@ -0,0 +1,391 @@
+use std::str::FromStr;
+
+/// A height function written in a small expression language, like
+/// `noise(x * 2, y * 2) * 0.5 + ridge(x, y)`.
+///
+/// The language has numbers, the `x` and `y` variables, the `pi` constant, the `+`, `-`, `*`, `/`
+/// and `^` operators, parentheses and the functions listed in `Func`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f32),
+    X,
+    Y,
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// The functions available in expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    /// `noise(x, y)`, simplex noise in [-1, 1] with features about 1 unit apart, the same as
+    /// `--noise simplex`.
+    Noise,
+
+    /// `fbm(x, y)` or `fbm(x, y, octaves)`, octaves of `noise` whose frequency and amplitude
+    /// change by `lacunarity` and `gain`, the same as `--noise fbm`, in [-1, 1].
+    Fbm,
+
+    /// `ridge(x, y)` or `ridge(x, y, octaves)`, like `fbm` but every octave is folded into sharp
+    /// crests, in [0, 1].
+    Ridge,
+
+    Sin,
+    Cos,
+    Abs,
+    Sqrt,
+    Exp,
+    Floor,
+    Min,
+    Max,
+    Pow,
+    Clamp,
+}
+
+/// The values an expression is evaluated with.
+pub struct Context<'a> {
+    pub x: f32,
+    pub y: f32,
+    pub noise: &'a Simplex,
+}
+
+/// The seeded noise functions of the expressions, which are the simplex noise of simdnoise that
+/// `--noise` uses too.
+pub struct Simplex {
+    pub seed: i32,
+
+    /// The number of octaves of `fbm` and `ridge` when not given.
+    pub octaves: u8,
+    pub gain: f32,
+    pub lacunarity: f32,
+}
+
+/// The raw 2d simplex noise of simdnoise stays within about 1 / 45 from 0.
+const SIMPLEX_SCALE: f32 = 45.0;
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        Some(match name {
+            "noise" => Func::Noise,
+            "fbm" => Func::Fbm,
+            "ridge" => Func::Ridge,
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "abs" => Func::Abs,
+            "sqrt" => Func::Sqrt,
+            "exp" => Func::Exp,
+            "floor" => Func::Floor,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "pow" => Func::Pow,
+            "clamp" => Func::Clamp,
+            _ => return None,
+        })
+    }
+
+    /// The minimum and maximum number of arguments.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Func::Fbm | Func::Ridge => (2, 3),
+            Func::Noise | Func::Min | Func::Max | Func::Pow => (2, 2),
+            Func::Clamp => (3, 3),
+            Func::Sin | Func::Cos | Func::Abs | Func::Sqrt | Func::Exp | Func::Floor => (1, 1),
+        }
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, ctx: &Context) -> f32 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::X => ctx.x,
+            Expr::Y => ctx.y,
+            Expr::Neg(e) => -e.eval(ctx),
+            Expr::Bin(op, l, r) => {
+                let (l, r) = (l.eval(ctx), r.eval(ctx));
+                match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    BinOp::Pow => l.powf(r),
+                }
+            }
+            Expr::Call(f, args) => {
+                let a = args.iter().map(|a| a.eval(ctx)).collect::<Vec<_>>();
+                let octaves = a
+                    .get(2)
+                    .map_or(ctx.noise.octaves, |o| o.clamp(1.0, 255.0) as u8)
+                    .max(1);
+
+                match f {
+                    Func::Noise => ctx.noise.noise(a[0], a[1]),
+                    Func::Fbm => ctx.noise.fbm(a[0], a[1], octaves),
+                    Func::Ridge => ctx.noise.ridge(a[0], a[1], octaves),
+                    Func::Sin => a[0].sin(),
+                    Func::Cos => a[0].cos(),
+                    Func::Abs => a[0].abs(),
+                    Func::Sqrt => a[0].sqrt(),
+                    Func::Exp => a[0].exp(),
+                    Func::Floor => a[0].floor(),
+                    Func::Min => a[0].min(a[1]),
+                    Func::Max => a[0].max(a[1]),
+                    Func::Pow => a[0].powf(a[1]),
+                    Func::Clamp => a[0].max(a[1]).min(a[2]),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected `{}`", t)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Sym(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Ident(i) => write!(f, "{}", i),
+            Token::Sym(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let n = s[start..end]
+                .parse()
+                .map_err(|_| format!("`{}` is not a valid number", &s[start..end]))?;
+            tokens.push(Token::Num(n));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(s[start..end].to_string()));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Sym(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected `{}`", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, from the lowest to the highest precedence: sums, products, unary
+/// minus, powers, which are right associative, and atoms.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, sym: char) -> bool {
+        if self.peek() == Some(&Token::Sym(sym)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: char) -> Result<(), String> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(t) => format!("expected `{}` but got `{}`", sym, t),
+                None => format!("expected `{}` but the expression ended", sym),
+            })
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            // -x ^ 2 is -(x ^ 2) while x ^ -2 is x ^ (-2)
+            let exponent = self.unary()?;
+            Ok(Expr::Bin(BinOp::Pow, Box::new(base), Box::new(exponent)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| "the expression ended unexpectedly".to_string())?;
+        self.pos += 1;
+
+        match token {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Sym('(') => {
+                let e = self.expr()?;
+                self.expect(')')?;
+                Ok(e)
+            }
+            Token::Ident(name) => match name.as_str() {
+                "x" => Ok(Expr::X),
+                "y" => Ok(Expr::Y),
+                "pi" => Ok(Expr::Num(std::f32::consts::PI)),
+                _ => {
+                    let f = Func::from_name(&name)
+                        .ok_or_else(|| format!("unknown variable or function `{}`", name))?;
+                    self.expect('(')?;
+
+                    let mut args = vec![];
+                    if !self.eat(')') {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(')') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+
+                    let (min, max) = f.arity();
+                    if args.len() < min || args.len() > max {
+                        return Err(format!(
+                            "`{}` takes {} arguments but got {}",
+                            name,
+                            if min == max {
+                                min.to_string()
+                            } else {
+                                format!("{} to {}", min, max)
+                            },
+                            args.len()
+                        ));
+                    }
+
+                    Ok(Expr::Call(f, args))
+                }
+            },
+            Token::Sym(c) => Err(format!("unexpected `{}`", c)),
+        }
+    }
+}
+
+impl Simplex {
+    /// Simplex noise in [-1, 1].
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        // the scalar functions are unsafe only to share the signature of the simd ones, they
+        // don't need any cpu feature
+        let n = unsafe { simdnoise::scalar::simplex_2d(x, y, self.seed) };
+        (n * SIMPLEX_SCALE).clamp(-1.0, 1.0)
+    }
+
+    /// The octaves of `noise` summed like `fbm` of simdnoise, in [-1, 1].
+    pub fn fbm(&self, x: f32, y: f32, octaves: u8) -> f32 {
+        let n = unsafe {
+            simdnoise::scalar::fbm_2d(x, y, self.lacunarity, self.gain, octaves, self.seed)
+        };
+        (n * SIMPLEX_SCALE / self.total_amplitude(octaves)).clamp(-1.0, 1.0)
+    }
+
+    /// The octaves of `1 - |noise|` summed like `ridge` of simdnoise, in [0, 1].
+    pub fn ridge(&self, x: f32, y: f32, octaves: u8) -> f32 {
+        let n = unsafe {
+            simdnoise::scalar::ridge_2d(x, y, self.lacunarity, self.gain, octaves, self.seed)
+        };
+
+        // every octave adds 1 - |noise| * amplitude
+        let folded = (f32::from(octaves) - n) * SIMPLEX_SCALE / self.total_amplitude(octaves);
+        (1.0 - folded).clamp(0.0, 1.0)
+    }
+
+    fn total_amplitude(&self, octaves: u8) -> f32 {
+        (0..octaves).map(|i| self.gain.powi(i32::from(i))).sum()
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::overlay::{self, CarveConfig, MaskConfig};
+use crate::{grid, quantize, App, Terrain};
+
+#[derive(Parser)]
+pub struct FilterConfig {
+    /// Post-process the terrain with the given filter, written as `name` or `name:argument`. Can
+    /// be repeated, the filters are applied in order after `carve` and `mask`. The available
+    /// filters are `blur:SIGMA`, `erode:ITERATIONS` and `terrace:LEVELS`.
+    #[arg(long = "filter")]
+    pub filters: Vec<String>,
+
+    /// File with one filter per line, in the same form as `filter`, that are applied before the
+    /// ones given on the command line. Empty lines and lines starting with `#` are ignored.
+    #[arg(long = "filter-file")]
+    pub filter_file: Option<PathBuf>,
+}
+
+/// A post-processing step that modifies the terrain in place.
+pub trait TerrainFilter {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()>;
+}
+
+/// Build a filter from the argument that follows its name in a filter spec, if any.
+pub type FilterConstructor = fn(Option<&str>) -> Result<Box<dyn TerrainFilter>, String>;
+
+/// The filters that can be referenced by name in a filter chain.
+pub struct FilterRegistry {
+    constructors: BTreeMap<&'static str, FilterConstructor>,
+}
+
+impl FilterRegistry {
+    /// A registry that contains all the filters that come with terrain-mesh.
+    pub fn with_builtins() -> Self {
+        let mut registry = FilterRegistry {
+            constructors: BTreeMap::new(),
+        };
+
+        registry.register("blur", |arg| {
+            let sigma = parse_arg(arg, 1.0)?;
+            Ok(Box::new(Blur { sigma }))
+        });
+        registry.register("erode", |arg| {
+            let iterations = parse_arg(arg, 10)?;
+            Ok(Box::new(Erode { iterations }))
+        });
+        registry.register("terrace", |arg| {
+            let levels = parse_arg(arg, 0)?;
+            if levels < 2 {
+                return Err("terrace needs at least 2 levels".to_string());
+            }
+            Ok(Box::new(Terrace { levels }))
+        });
+
+        registry
+    }
+
+    /// Make the filter available under the given name, replacing the one with the same name, if
+    /// any.
+    pub fn register(&mut self, name: &'static str, constructor: FilterConstructor) {
+        self.constructors.insert(name, constructor);
+    }
+
+    /// Build the filter described by a spec in the form `name` or `name:argument`.
+    pub fn parse(&self, spec: &str) -> io::Result<Box<dyn TerrainFilter>> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (spec.trim(), None),
+        };
+
+        let constructor = self.constructors.get(name).ok_or_else(|| {
+            invalid(format!(
+                "unknown filter `{}`, available filters are: {}",
+                name,
+                self.constructors
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })?;
+
+        constructor(arg).map_err(|e| invalid(format!("invalid filter `{}`: {}", spec, e)))
+    }
+}
+
+/// Build the chain of filters to apply to the terrain: carving and masking first, if enabled,
+/// followed by the filters in the filter file and then the ones on the command line.
+pub fn pipeline<'a>(
+    opt: &'a App,
+    registry: &FilterRegistry,
+) -> io::Result<Vec<Box<dyn TerrainFilter + 'a>>> {
+    let mut filters: Vec<Box<dyn TerrainFilter + 'a>> = vec![];
+
+    if opt.carve.carve.is_some() {
+        filters.push(Box::new(Carve { cfg: &opt.carve }));
+    }
+
+    if opt.mask.mask.is_some() {
+        filters.push(Box::new(Mask { cfg: &opt.mask }));
+    }
+
+    if let Some(path) = &opt.filter.filter_file {
+        let specs = fs::read_to_string(path)?;
+
+        for spec in specs.lines().map(str::trim) {
+            if spec.is_empty() || spec.starts_with('#') {
+                continue;
+            }
+
+            filters.push(registry.parse(spec)?);
+        }
+    }
+
+    for spec in &opt.filter.filters {
+        filters.push(registry.parse(spec)?);
+    }
+
+    Ok(filters)
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<&str>, default: T) -> Result<T, String> {
+    match arg {
+        None => Ok(default),
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| format!("`{}` is not a valid argument", arg)),
+    }
+}
+
+/// Gaussian blur of the heights.
+struct Blur {
+    sigma: f32,
+}
+
+impl TerrainFilter for Blur {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()> {
+        let heights = std::mem::take(&mut terrain.heights);
+        terrain.heights = grid::blur(heights, terrain.width, terrain.depth, self.sigma);
+        Ok(())
+    }
+}
+
+/// Thermal erosion, the talus is relative to the amplitude of the terrain.
+struct Erode {
+    iterations: usize,
+}
+
+impl TerrainFilter for Erode {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()> {
+        let scale = terrain.amplitude.max(f32::EPSILON);
+
+        let mut heights = terrain
+            .heights
+            .iter()
+            .map(|h| h / scale)
+            .collect::<Vec<_>>();
+        for _ in 0..self.iterations {
+            grid::erode(&mut heights, terrain.width, terrain.depth);
+        }
+
+        terrain.heights = heights.into_iter().map(|h| h * scale).collect();
+        Ok(())
+    }
+}
+
+/// Snap the heights to evenly spaced levels between the lowest and the highest point, leaving
+/// flat terraces with steep steps between them.
+struct Terrace {
+    levels: u16,
+}
+
+impl TerrainFilter for Terrace {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()> {
+        let (lo, hi) = terrain
+            .heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+
+        let levels = quantize::levels(lo, hi - lo, self.levels);
+        terrain.map_heights(|_, _, h| quantize::snap(h, &levels));
+        Ok(())
+    }
+}
+
+/// Carve the paths of the `carve` GeoJSON file into the terrain.
+struct Carve<'a> {
+    cfg: &'a CarveConfig,
+}
+
+impl TerrainFilter for Carve<'_> {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()> {
+        if let Some(path) = &self.cfg.carve {
+            let paths = overlay::load_geojson(path, terrain, &["LineString", "MultiLineString"])?;
+            overlay::carve(terrain, &paths, self.cfg);
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply the polygons of the `mask` GeoJSON file to the terrain.
+struct Mask<'a> {
+    cfg: &'a MaskConfig,
+}
+
+impl TerrainFilter for Mask<'_> {
+    fn apply(&self, terrain: &mut Terrain) -> io::Result<()> {
+        if let Some(path) = &self.cfg.mask {
+            let polygons = overlay::load_geojson(path, terrain, &["Polygon", "MultiPolygon"])?;
+            overlay::apply_polygons(terrain, &polygons, self.cfg.mask_mode);
+        }
+
+        Ok(())
+    }
+}
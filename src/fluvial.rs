@@ -0,0 +1,166 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+use simdnoise::NoiseBuilder;
+
+use clap::Parser;
+
+use crate::grid::{blur, normalize};
+
+#[derive(Parser)]
+pub struct FluvialConfig {
+    /// The width of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub width: u16,
+
+    /// The depth of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub depth: u16,
+
+    /// The seed to use to generate the terrain. You can find the seed of a given terrain by
+    /// inspecting the obj file.
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// The number of points on the border of the terrain where the rivers flow out.
+    #[arg(long, default_value = "3")]
+    pub outlets: usize,
+
+    /// How much the rivers meander, 0 makes them flow straight to the closest outlet.
+    #[arg(long, default_value = "2.0")]
+    pub meandering: f32,
+
+    /// How quickly the slope decreases with the drainage area, higher values lead to flatter
+    /// valley floors and steeper hillslopes.
+    #[arg(long, default_value = "0.45")]
+    pub concavity: f32,
+
+    /// The maximum height of the terrain. If `base-thickness` is specified then the final mesh has
+    /// a potential maximum height of `base-thickness` + `amplitude`.
+    #[arg(short, long, default_value = "20")]
+    pub amplitude: f32,
+
+    /// The thickness of the base upon which the terrain is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    pub base_thickness: f32,
+}
+
+/// Synthesize a drainage network and build a terrain that is consistent with it, returning the
+/// heights normalized in [0, 1] in row major order.
+///
+/// The network is a random spanning tree grown from the outlets, biased by noise so that the
+/// main rivers follow large scale features instead of wandering uniformly. The heights are then
+/// integrated upstream from the outlets following the stream power law, that is the slope of
+/// every cell decreases with the area that drains through it, so that the valleys follow the
+/// rivers by construction.
+pub fn simulate(cfg: &FluvialConfig, seed: u64) -> Vec<f32> {
+    let width = usize::from(cfg.width);
+    let depth = usize::from(cfg.depth);
+    let mut rng = Pcg32::seed_from_u64(seed);
+
+    let bias = NoiseBuilder::fbm_2d(width, depth)
+        .with_freq(0.05)
+        .with_octaves(3)
+        .with_seed(rng.gen())
+        .generate_scaled(0.0, 1.0);
+
+    let border = (0..width)
+        .map(|x| (x, 0))
+        .chain((0..depth).map(|y| (width - 1, y)))
+        .chain((0..width).map(|x| (x, depth - 1)))
+        .chain((0..depth).map(|y| (0, y)))
+        .collect::<Vec<_>>();
+
+    let mut distances = vec![0.0_f32; width * depth];
+    let mut downstream = vec![None; width * depth];
+    let mut visited = vec![false; width * depth];
+    let mut heap = BinaryHeap::new();
+    for &(x, y) in border.choose_multiple(&mut rng, cfg.outlets.max(1)) {
+        // the bits of non negative floats sort like the floats themselves
+        heap.push(Reverse((
+            0_f32.to_bits(),
+            y * width + x,
+            None,
+            0_f32.to_bits(),
+        )));
+    }
+
+    // grow a random spanning tree from the outlets, cells are added in order from the outlets
+    // upstream
+    let mut order = vec![];
+    while let Some(Reverse((_, i, from, d))) = heap.pop() {
+        if visited[i] {
+            continue;
+        }
+        visited[i] = true;
+        downstream[i] = from;
+        distances[i] = f32::from_bits(d);
+        order.push(i);
+
+        let (x, y) = (i % width, i / width);
+        for (dx, dy) in NEIGHBORS {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= depth {
+                continue;
+            }
+
+            let j = ny as usize * width + nx as usize;
+            if !visited[j] {
+                let d = distances[i] + ((dx * dx + dy * dy) as f32).sqrt() * (1.0 + bias[j]);
+                let weight = d + cfg.meandering.max(0.0) * MEANDER_SCALE * rng.gen::<f32>();
+                heap.push(Reverse((weight.to_bits(), j, Some(i), d.to_bits())));
+            }
+        }
+    }
+
+    let mut area = vec![1.0_f32; width * depth];
+    for &i in order.iter().rev() {
+        if let Some(j) = downstream[i] {
+            area[j] += area[i];
+        }
+    }
+
+    let mut heights = vec![0.0; width * depth];
+    for &i in &order {
+        if let Some(j) = downstream[i] {
+            let (x0, y0) = ((i % width) as f32, (i / width) as f32);
+            let (x1, y1) = ((j % width) as f32, (j / width) as f32);
+
+            heights[i] = heights[j] + (x1 - x0).hypot(y1 - y0) * area[i].powf(-cfg.concavity);
+        }
+    }
+
+    // neighboring hillslope cells may drain along different paths of the grid, smooth the
+    // resulting streaks
+    let mut heights = blur(heights, width, depth, 1.5);
+    normalize(&mut heights);
+
+    let detail = NoiseBuilder::fbm_2d(width, depth)
+        .with_freq(0.15)
+        .with_octaves(3)
+        .with_seed(rng.gen())
+        .generate_scaled(0.0, 0.02);
+    for ((h, d), a) in heights.iter_mut().zip(detail).zip(&area) {
+        // keep the riverbeds smooth
+        *h += d / a.sqrt();
+    }
+
+    normalize(&mut heights);
+    heights
+}
+
+/// How many cells the paths can deviate from the shortest one for every unit of meandering.
+const MEANDER_SCALE: f32 = 4.0;
+
+const NEIGHBORS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
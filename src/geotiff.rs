@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+use crate::grid::Raster;
+
+/// Read the first band of the GeoTIFF at the given path.
+///
+/// The samples equal to the GDAL nodata value, if any, and the non finite ones are considered
+/// missing. The scale and the offset in the GDAL metadata, if any, are applied to the others.
+pub fn read_raster(path: &Path) -> io::Result<Raster> {
+    let invalid = |e: tiff::TiffError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a valid GeoTIFF: {}", path.display(), e),
+        )
+    };
+
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(invalid)?;
+
+    if !matches!(decoder.colortype().map_err(invalid)?, ColorType::Gray(_)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} doesn't contain a single band", path.display()),
+        ));
+    }
+
+    let (width, depth) = decoder.dimensions().map_err(invalid)?;
+
+    let nodata = decoder
+        .get_tag_ascii_string(Tag::GdalNodata)
+        .ok()
+        .and_then(|s| s.trim_matches(char::from(0)).trim().parse::<f64>().ok());
+
+    let metadata = decoder
+        .get_tag_ascii_string(Tag::Unknown(GDAL_METADATA))
+        .unwrap_or_default();
+    let scale = metadata_item(&metadata, "SCALE").unwrap_or(1.0);
+    let offset = metadata_item(&metadata, "OFFSET").unwrap_or(0.0);
+
+    let values: Vec<f64> = match decoder.read_image().map_err(invalid)? {
+        DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U64(v) => v.into_iter().map(|s| s as f64).collect(),
+        DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I64(v) => v.into_iter().map(|s| s as f64).collect(),
+        DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(v) => v,
+    };
+
+    let samples = values
+        .into_iter()
+        .map(|v| {
+            if !v.is_finite() || nodata == Some(v) {
+                None
+            } else {
+                Some((v * scale + offset) as f32)
+            }
+        })
+        .collect();
+
+    Ok(Raster {
+        width: width as usize,
+        depth: depth as usize,
+        samples,
+    })
+}
+
+/// The tag where GDAL stores the metadata of the raster as XML.
+const GDAL_METADATA: u16 = 42112;
+
+/// Find the value of the metadata item with the given name, like
+/// `<Item name="SCALE" sample="0" role="scale">0.1</Item>`.
+fn metadata_item(metadata: &str, name: &str) -> Option<f64> {
+    let start = metadata.find(&format!("name=\"{}\"", name))?;
+    let rest = &metadata[start..];
+    let value = &rest[rest.find('>')? + 1..];
+
+    value[..value.find('<')?].trim().parse().ok()
+}
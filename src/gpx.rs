@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::dem::METERS_PER_DEGREE;
+use crate::grid::{blur, normalize};
+
+#[derive(Parser)]
+pub struct GpxConfig {
+    /// Input GPX file. The points of all its tracks and routes are joined in order, the points
+    /// without an elevation are skipped.
+    #[arg()]
+    pub gpx: PathBuf,
+
+    /// The number of vertices along the track, that is the length of the ribbon. The track is
+    /// resampled at evenly spaced distances.
+    #[arg(short, long, default_value = "201")]
+    pub width: u16,
+
+    /// The width of the ribbon, across the track, in cells.
+    #[arg(long = "ribbon-width", default_value = "10")]
+    pub ribbon_width: u16,
+
+    /// How much to smooth the elevation profile, in cells, to hide the noise of the GPS.
+    /// Smoothing is performed via a Gaussian blur.
+    #[arg(short, long, default_value = "1.0")]
+    pub smoothness: f32,
+
+    /// The maximum height of the terrain. If `base-thickness` is specified then the final mesh has
+    /// a potential maximum height of `base-thickness` + `amplitude`.
+    #[arg(short, long, default_value = "20")]
+    pub amplitude: f32,
+
+    /// The thickness of the base upon which the terrain is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    pub base_thickness: f32,
+}
+
+/// A point of a track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub lon: f64,
+    pub lat: f64,
+    pub elevation: f64,
+}
+
+/// The elevation profile of the track as `width` samples in [0, 1] at evenly spaced distances
+/// from the start.
+pub fn profile(cfg: &GpxConfig) -> io::Result<Vec<f32>> {
+    let points = read_gpx(&cfg.gpx)?;
+    let width = usize::from(cfg.width).max(2);
+
+    let mut distances = vec![0.0];
+    for w in points.windows(2) {
+        distances.push(distances.last().unwrap() + haversine(&w[0], &w[1]));
+    }
+    let total = *distances.last().unwrap();
+    if total <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the track of {} has no length", cfg.gpx.display()),
+        ));
+    }
+
+    let mut segment = 0;
+    let mut samples = (0..width)
+        .map(|i| {
+            let d = total * i as f64 / (width - 1) as f64;
+            while segment + 2 < distances.len() && distances[segment + 1] < d {
+                segment += 1;
+            }
+
+            let (d0, d1) = (distances[segment], distances[segment + 1]);
+            let t = if d1 > d0 { (d - d0) / (d1 - d0) } else { 0.0 };
+            let (e0, e1) = (points[segment].elevation, points[segment + 1].elevation);
+            (e0 + (e1 - e0) * t.clamp(0.0, 1.0)) as f32
+        })
+        .collect::<Vec<_>>();
+
+    samples = blur(samples, width, 1, cfg.smoothness);
+    normalize(&mut samples);
+
+    Ok(samples)
+}
+
+/// Read the points of the tracks and the routes of a GPX file that have an elevation.
+pub fn read_gpx(path: &Path) -> io::Result<Vec<TrackPoint>> {
+    let text = fs::read_to_string(path)?;
+
+    let mut points = vec![];
+    let mut rest = text.as_str();
+    while let Some(start) = ["<trkpt", "<rtept"]
+        .iter()
+        .filter_map(|tag| rest.find(tag))
+        .min()
+    {
+        rest = &rest[start..];
+
+        // a point is either `<trkpt .../>` or `<trkpt ...>...</trkpt>`
+        let open_end = rest.find('>').unwrap_or(rest.len());
+        let attrs = &rest[..open_end];
+        let (body, next) = if attrs.ends_with('/') {
+            ("", open_end)
+        } else {
+            let close = ["</trkpt>", "</rtept>"]
+                .iter()
+                .filter_map(|tag| rest.find(tag))
+                .min()
+                .unwrap_or(rest.len());
+            (&rest[open_end.min(close)..close], close)
+        };
+        rest = &rest[next.max(1)..];
+
+        let lat = attribute(attrs, "lat");
+        let lon = attribute(attrs, "lon");
+        let elevation = element(body, "ele");
+        if let (Some(lat), Some(lon), Some(elevation)) = (lat, lon, elevation) {
+            points.push(TrackPoint {
+                lon,
+                lat,
+                elevation,
+            });
+        }
+    }
+
+    if points.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} doesn't contain at least 2 track points with an elevation",
+                path.display()
+            ),
+        ));
+    }
+
+    Ok(points)
+}
+
+/// The value of the attribute with the given name in an XML tag, quoted with either `"` or `'`.
+fn attribute(tag: &str, name: &str) -> Option<f64> {
+    let mut rest = tag;
+    loop {
+        let start = rest.find(name)?;
+        let preceded_by_space = rest[..start].ends_with(char::is_whitespace);
+        rest = rest[start + name.len()..].trim_start();
+
+        if preceded_by_space && rest.starts_with('=') {
+            let value = rest[1..].trim_start();
+            let quote = value.chars().next()?;
+            let value = &value[1..];
+            return value[..value.find(quote)?].trim().parse().ok();
+        }
+    }
+}
+
+/// The text of the first element with the given name, like `<ele>123.4</ele>`.
+fn element(xml: &str, name: &str) -> Option<f64> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", name))?;
+    xml[start..start + end].trim().parse().ok()
+}
+
+/// The distance in meters between the two points along the surface of the earth.
+fn haversine(a: &TrackPoint, b: &TrackPoint) -> f64 {
+    let radius = METERS_PER_DEGREE * 180.0 / std::f64::consts::PI;
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let dlat = lat_b - lat_a;
+    let dlon = (b.lon - a.lon).to_radians();
+
+    let h = (dlat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * radius * h.sqrt().asin()
+}
@@ -0,0 +1,120 @@
+/// The elevations of a heightmap in row major order from the top left corner, missing samples are
+/// `None`.
+#[derive(Debug)]
+pub struct Raster {
+    pub width: usize,
+    pub depth: usize,
+    pub samples: Vec<Option<f32>>,
+}
+
+/// The steepest difference of height between two adjacent cells that thermal erosion leaves
+/// untouched.
+const TALUS: f32 = 0.02;
+
+/// Gaussian blur the row major grid of values, clamping to the edges.
+///
+/// Unlike `image::imageops::blur` this doesn't clamp the values in [0, 1].
+pub fn blur(values: Vec<f32>, width: usize, depth: usize, sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return values;
+    }
+
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let total = kernel.iter().sum::<f32>();
+
+    let pass = |values: &[f32], step: (usize, usize)| {
+        (0..width * depth)
+            .map(|i| {
+                let (x, y) = ((i % width) as isize, (i / width) as isize);
+
+                kernel
+                    .iter()
+                    .zip(-radius..=radius)
+                    .map(|(k, o)| {
+                        let sx = (x + o * step.0 as isize).clamp(0, width as isize - 1);
+                        let sy = (y + o * step.1 as isize).clamp(0, depth as isize - 1);
+                        k * values[sy as usize * width + sx as usize]
+                    })
+                    .sum::<f32>()
+                    / total
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let horizontal = pass(&values, (1, 0));
+    pass(&horizontal, (0, 1))
+}
+
+/// Run a single iteration of thermal erosion, moving material from every cell to its lowest
+/// neighbor when the difference is steeper than the talus.
+pub fn erode(heights: &mut [f32], width: usize, depth: usize) {
+    let mut delta = vec![0.0; heights.len()];
+
+    for y in 0..depth {
+        for x in 0..width {
+            let i = y * width + x;
+
+            let lowest = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .map(|&(dx, dy)| (x as isize + dx, y as isize + dy))
+                .filter(|&(nx, ny)| {
+                    nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < depth
+                })
+                .map(|(nx, ny)| ny as usize * width + nx as usize)
+                .min_by(|&a, &b| heights[a].partial_cmp(&heights[b]).unwrap());
+
+            if let Some(j) = lowest {
+                let diff = heights[i] - heights[j];
+                if diff > TALUS {
+                    let amount = (diff - TALUS) / 4.0;
+                    delta[i] -= amount;
+                    delta[j] += amount;
+                }
+            }
+        }
+    }
+
+    for (h, d) in heights.iter_mut().zip(delta) {
+        *h += d;
+    }
+}
+
+/// Linearly remap the heights in [0, 1].
+pub fn normalize(heights: &mut [f32]) {
+    let (lo, hi) = heights
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+            (lo.min(h), hi.max(h))
+        });
+
+    let range = if hi > lo { hi - lo } else { 1.0 };
+    for h in heights {
+        *h = (*h - lo) / range;
+    }
+}
+
+/// Remap the samples from their own range to [0, 1], the missing ones become 0 so that they
+/// can't raise spikes along the edges of the footprint once blurred. Returns `None` when all the
+/// samples are missing.
+pub fn normalize_samples(samples: &[Option<f32>]) -> Option<Vec<f32>> {
+    let (lo, hi) = samples
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &s| {
+            (lo.min(s), hi.max(s))
+        });
+    if lo > hi {
+        return None;
+    }
+    let range = (hi - lo).max(f32::EPSILON);
+
+    Some(
+        samples
+            .iter()
+            .map(|s| s.map_or(0.0, |s| (s - lo) / range))
+            .collect(),
+    )
+}
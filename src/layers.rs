@@ -0,0 +1,189 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::encoding::Encoding;
+use crate::mesh::Mesh;
+use crate::output::Outputs;
+use crate::report::Reporter;
+use crate::xyz;
+use crate::{
+    mesh_comments, terrain_mesh, write_mesh, App, Format, HeightmapConfig, Submerged, Terrain,
+};
+
+#[derive(Parser)]
+pub struct LayersConfig {
+    /// Input grayscale heightmaps, one per surface ordered from the lowest to the highest. All
+    /// the heightmaps must have the same size.
+    #[arg(required = true)]
+    heightmaps: Vec<PathBuf>,
+
+    /// The maximum height of the surfaces. If `base-thickness` is specified then the final mesh
+    /// has a potential maximum height of `base-thickness` + `amplitude`.
+    #[arg(short, long, default_value = "20")]
+    amplitude: f32,
+
+    /// The thickness of the base upon which the lowest layer is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    base_thickness: f32,
+
+    /// How much to smooth the grayscale images before turning them into meshes. Smoothing is
+    /// performed via a Gaussian blur.
+    #[arg(short, long, default_value = "0.3")]
+    smoothness: f32,
+}
+
+/// Load all the surfaces and write one solid per layer, each one nesting on top of the previous.
+/// The first layer sits on the base plane while the others are bounded below by the surface of
+/// the previous layer. Surfaces that dip below the previous one are raised to meet it so that the
+/// layers never intersect.
+pub fn write_layers(
+    cfg: &LayersConfig,
+    opt: &App,
+    outputs: &Outputs,
+    reporter: &Reporter,
+) -> image::ImageResult<()> {
+    let format = opt.format();
+    if format == Format::Npy {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "layers can only be written as meshes",
+        )
+        .into());
+    }
+
+    let unsupported = [
+        ("sea-level", opt.sea_level.is_some()),
+        ("submerged", opt.submerged != Submerged::Merged),
+        ("scale-bar and north-arrow", opt.engrave.is_enabled()),
+        ("magnet, screw and keyhole", opt.mount.is_enabled()),
+        ("carve", opt.carve.carve.is_some()),
+        ("mask", opt.mask.mask.is_some()),
+        (
+            "filter and filter-file",
+            !opt.filter.filters.is_empty() || opt.filter.filter_file.is_some(),
+        ),
+        ("bake-splat", opt.splat.bake_splat.is_some()),
+        ("render", opt.render.render.is_some()),
+        ("dual", opt.dual),
+    ];
+    let unsupported = unsupported
+        .iter()
+        .filter(|(_, used)| *used)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("layers can't be combined with {}", unsupported.join(", ")),
+        )
+        .into());
+    }
+
+    let mut surfaces: Vec<Terrain> = vec![];
+
+    for path in &cfg.heightmaps {
+        let mut surface = Terrain::from_heightmap(&HeightmapConfig {
+            grayscale_heightmap: path.clone(),
+            amplitude: cfg.amplitude,
+            base_thickness: cfg.base_thickness,
+            smoothness: cfg.smoothness,
+            sea_level_pixel: None,
+            encoding: Encoding::Grayscale,
+            geotiff: false,
+            raw: None,
+            xyz: false,
+            xyz_spacing: None,
+            regrid: xyz::Regrid::Nearest,
+            quantize_levels: None,
+        })?;
+
+        if surface.has_mask() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} has transparent pixels, which layers doesn't support",
+                    path.display()
+                ),
+            )
+            .into());
+        }
+
+        if let Some(below) = surfaces.last() {
+            if (below.width, below.depth) != (surface.width, surface.depth) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} has a different size than the others", path.display()),
+                )
+                .into());
+            }
+
+            for (h, bh) in surface.heights.iter_mut().zip(&below.heights) {
+                *h = h.max(*bh);
+            }
+        }
+
+        surfaces.push(surface);
+    }
+
+    for i in 0..surfaces.len() {
+        outputs.plan(&outputs.path_of("layer", Some(i), format.extension()))?;
+    }
+
+    for (i, surface) in surfaces.iter().enumerate() {
+        let path = outputs.path("layer", Some(i), format.extension())?;
+        let mesh = match i.checked_sub(1) {
+            None => terrain_mesh(surface, true, false, None, None),
+            Some(below) => mesh_between(surface, &surfaces[below]),
+        };
+
+        write_mesh(&path, &mesh, surface, opt, outputs, reporter)?;
+    }
+
+    Ok(())
+}
+
+/// Build the closed solid enclosed between the `top` and `bottom` surfaces which must have the
+/// same size.
+pub fn mesh_between(top: &Terrain, bottom: &Terrain) -> Mesh {
+    let mut mesh = Mesh::new(mesh_comments(top));
+
+    for (y, x, z) in top.iter_by_depth() {
+        mesh.add_vertex([x as f32, y as f32, z]);
+    }
+    for (y, x, z) in bottom.iter_by_depth() {
+        mesh.add_vertex([x as f32, y as f32, z]);
+    }
+
+    let depth = top.depth();
+    let width = top.width();
+    let oi = width * depth;
+
+    for y in 0..depth.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let i = top.index_of(x, y);
+            let j = top.index_of(x, y + 1);
+            mesh.add_face(vec![i, i + 1, j + 1, j], None);
+            mesh.add_face(vec![oi + i, oi + j, oi + j + 1, oi + i + 1], None);
+        }
+    }
+
+    for y in 0..depth.saturating_sub(1) {
+        let (i, j) = (top.index_of(0, y), top.index_of(0, y + 1));
+        mesh.add_face(vec![oi + j, oi + i, i, j], None);
+
+        let (i, j) = (top.index_of(width - 1, y), top.index_of(width - 1, y + 1));
+        mesh.add_face(vec![oi + i, oi + j, j, i], None);
+    }
+
+    for x in 0..width.saturating_sub(1) {
+        let (i, j) = (top.index_of(x, 0), top.index_of(x + 1, 0));
+        mesh.add_face(vec![oi + i, oi + j, j, i], None);
+
+        let (i, j) = (top.index_of(x, depth - 1), top.index_of(x + 1, depth - 1));
+        mesh.add_face(vec![oi + j, oi + i, i, j], None);
+    }
+
+    mesh
+}
@@ -2,27 +2,134 @@ use std::env;
 use std::fs::File;
 use std::io;
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use rand::prelude::*;
 use rand_pcg::Pcg32;
 
-use clap::{Parser, Subcommand};
-use simdnoise::NoiseBuilder;
+use clap::{Parser, Subcommand, ValueEnum};
+use coastline::Side;
+use engrave::{Engraving, WallVertex};
+use image::ColorType;
+use mesh::{Material, Mesh};
+use mount::{BaseVertex, Mounting};
+use output::Outputs;
+use report::Reporter;
+use simdnoise::{CellDistanceFunction, CellReturnType, NoiseBuilder};
+
+mod coastline;
+mod composite;
+mod dem;
+mod dunes;
+mod encoding;
+mod engrave;
+mod expr;
+mod filter;
+mod fluvial;
+mod geotiff;
+mod gpx;
+mod grid;
+mod layers;
+mod mesh;
+mod mount;
+mod npy;
+mod off;
+mod output;
+mod overlay;
+mod quantize;
+mod raw;
+mod render;
+mod report;
+mod splat;
+mod stl;
+mod tectonic;
+mod threemf;
+mod triangulate;
+mod xyz;
 
 /// Generate a terrain mesh from a noise function or a heightmap. The final mesh should be ready to
 /// be 3d printed.
 #[derive(Parser)]
 pub struct App {
-    /// Output obj filename template.
+    /// Output filename template. `{kind}` is replaced with the kind of the output, like
+    /// `terrain`, `dual`, `seabed` or `layer`, `{index}` with the index of the output among the
+    /// ones of the same kind and `{ext}` with the extension of the format. When the template
+    /// doesn't contain them, the kind and the index are appended to the file name of the
+    /// secondary outputs, like `terrain-dual.obj`.
     #[arg(short, long, default_value = "terrain.obj")]
     output: PathBuf,
 
+    /// Fail instead of replacing the output files that already exist. Nothing is written if any
+    /// of the output files exists.
+    #[arg(long = "no-clobber")]
+    no_clobber: bool,
+
+    /// The format of the output file. Defaults to the one that matches the extension of the
+    /// output file, if any, or obj.
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Crop the solid to the region of the terrain above this height, generating walls along the
+    /// coastline so that islands don't sit on a flat slab.
+    #[arg(long = "sea-level")]
+    sea_level: Option<f32>,
+
+    /// How to output the portion of the terrain below the reference plane at z = 0, like the
+    /// seabed of a bathymetric heightmap.
+    #[arg(long, value_enum, default_value = "merged")]
+    submerged: Submerged,
+
+    #[command(flatten)]
+    splat: splat::SplatConfig,
+
+    #[command(flatten)]
+    render: render::RenderConfig,
+
+    #[command(flatten)]
+    engrave: engrave::EngraveConfig,
+
+    #[command(flatten)]
+    mount: mount::MountConfig,
+
+    #[command(flatten)]
+    carve: overlay::CarveConfig,
+
+    #[command(flatten)]
+    mask: overlay::MaskConfig,
+
+    #[command(flatten)]
+    filter: filter::FilterConfig,
+
+    #[command(flatten)]
+    threemf: threemf::ThreeMfConfig,
+
+    /// The maximum number of decimals of the coordinates in the text formats, trailing zeros are
+    /// dropped. By default the coordinates are written with all the digits needed to read back
+    /// the exact same value.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    precision: Option<u8>,
+
+    /// Compress the output files with gzip, adding the `.gz` extension. The files that go with
+    /// them, like the material library, are left uncompressed.
+    #[arg(long)]
+    compress: bool,
+
     /// Generate the dual of terrain too.
     #[arg(long)]
     dual: bool,
 
+    /// Split the faces of the mesh into triangles instead of writing quads. The stl and 3mf
+    /// formats only support triangles and are always triangulated.
+    #[arg(long)]
+    triangulate: bool,
+
+    /// Print a JSON object per generated file to stdout, one per line. Files that contain the
+    /// terrain report the seed, the vertex and face counts, and the min and max height. All the
+    /// files report the seconds elapsed since the start of the run in `duration`.
+    #[arg(long = "json-output")]
+    json_output: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -32,11 +139,130 @@ pub enum Command {
     /// Generate random terrain-like quad mesh using various types of noise functions.
     Random(RandomConfig),
 
-    /// Turn grayscale 8 bit heightmap into a mesh.
+    /// Turn grayscale 8 or 16 bit heightmap into a mesh.
     Heightmap(HeightmapConfig),
+
+    /// Turn a region of a real world digital elevation model into a mesh, keeping the correct
+    /// proportions between the horizontal and the vertical scale.
+    Dem(dem::DemConfig),
+
+    /// Turn a stack of grayscale heightmaps, like the surfaces of geological layers, into one
+    /// solid per layer so that the solids nest on top of each other.
+    Layers(layers::LayersConfig),
+
+    /// Simulate a handful of colliding tectonic plates followed by a coarse erosion pass to
+    /// generate continent-scale terrain with mountain belts along the plate boundaries. This is
+    /// experimental.
+    Tectonic(tectonic::TectonicConfig),
+
+    /// Synthesize a random river network and build the terrain around it so that the valleys
+    /// follow the rivers.
+    Fluvial(fluvial::FluvialConfig),
+
+    /// Simulate the wind moving sand around to generate a field of dunes.
+    Dunes(dunes::DunesConfig),
+
+    /// Extrude the elevation profile of a GPX track into a ridge, with the distance along the
+    /// track on the x axis, like a trophy of a hike or a ride.
+    Gpx(gpx::GpxConfig),
 }
 
-#[derive(Parser)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Wavefront obj mesh, ready to be 3d printed.
+    Obj,
+
+    /// Binary STL mesh, made only of triangles and without materials.
+    Stl,
+
+    /// 3mf package with the unit of the model, made only of triangles.
+    #[value(name = "3mf")]
+    ThreeMf,
+
+    /// Object file format mesh, as read by CGAL and most geometry processing tools.
+    Off,
+
+    /// Numpy float32 array of the heights of the terrain.
+    Npy,
+}
+
+impl App {
+    /// The format of the output files, either the one given explicitly or the one inferred from
+    /// the extension of the output template.
+    pub fn format(&self) -> Format {
+        self.format.unwrap_or_else(|| {
+            self.output
+                .extension()
+                .and_then(|ext| Format::from_str(&ext.to_string_lossy(), true).ok())
+                .unwrap_or(Format::Obj)
+        })
+    }
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Obj => "obj",
+            Format::Stl => "stl",
+            Format::ThreeMf => "3mf",
+            Format::Off => "off",
+            Format::Npy => "npy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Submerged {
+    /// Output the whole terrain as a single solid.
+    Merged,
+
+    /// Output a single solid, but assign a different material to the submerged surface so that
+    /// it can be colored differently. The solid is never cropped.
+    Colored,
+
+    /// Output the land and the submerged portion as two separate solids, the latter in a file
+    /// with the `-seabed` suffix.
+    Separate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Noise {
+    /// Fractal brownian motion, that is several octaves of noise of increasing frequency and
+    /// decreasing amplitude summed together.
+    Fbm,
+
+    /// The absolute value of fbm, which folds the terrain along the zero crossings: rounded
+    /// hills separated by a network of sharp, narrow canyons.
+    Billow,
+
+    /// Fbm where every octave is folded on its own before being summed, busy rolling hills
+    /// creased at every scale.
+    Turbulence,
+
+    /// A single octave of simplex noise, smooth rolling hills without the axis aligned artifacts
+    /// that fbm shows with few octaves. `octaves`, `gain` and `lacunarity` are ignored.
+    Simplex,
+
+    /// Cellular, also known as Worley, noise where the height grows with the distance from the
+    /// closest of a set of randomly scattered points, for alien and crystalline landscapes.
+    /// `octaves`, `gain` and `lacunarity` are ignored.
+    Cellular,
+}
+
+/// How the distance between a point and the center of a cell of cellular noise is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DistanceFunction {
+    /// The straight line distance, round cells.
+    Euclidean,
+
+    /// The sum of the distances along the axes, diamond shaped cells.
+    Manhattan,
+
+    /// A blend of the other two.
+    Natural,
+}
+
+#[derive(Parser, Clone)]
 pub struct RandomConfig {
     /// The width of the final terrain as in number of vertices.
     #[arg(short, long, default_value = "51")]
@@ -51,6 +277,51 @@ pub struct RandomConfig {
     #[arg(short, long)]
     seed: Option<u64>,
 
+    /// The noise function the heights are sampled from.
+    #[arg(long, value_enum, default_value = "fbm")]
+    noise: Noise,
+
+    /// How the distance from the cells of cellular noise is measured.
+    #[arg(long = "distance-function", value_enum, default_value = "euclidean")]
+    distance_function: DistanceFunction,
+
+    /// How far the centers of the cells of cellular noise can move from a regular grid, between
+    /// 0 and 1.
+    #[arg(long, default_value = "0.25")]
+    jitter: f32,
+
+    /// Domain warping: how far, in cells, the positions the noise is sampled at are displaced by
+    /// a second, smooth noise field. Warping bends and stretches the features as if the terrain
+    /// had been pushed around, which looks a lot more natural. 0 disables it.
+    #[arg(long, default_value = "0.0")]
+    warp: f32,
+
+    /// The frequency of the noise field that displaces the positions with `warp`, lower values
+    /// give broader bends.
+    #[arg(long = "warp-frequency", default_value = "0.05")]
+    warp_frequency: f32,
+
+    /// An additional layer of noise combined with the terrain, written as `NOISE` or
+    /// `NOISE:key=value,...`, like `fbm:frequency=0.01,amplitude=2,op=multiply`. The keys are
+    /// `frequency`, `octaves`, `gain` and `lacunarity`, which default to the ones of the
+    /// terrain, `seed`, `amplitude`, which weights the layer, and `op`, one of `add` (the
+    /// default), `multiply`, `min` and `max`. Can be repeated, the layers are combined in order
+    /// with every value remapped to [0, 1] first, and the result is remapped to the amplitude of
+    /// the terrain.
+    #[arg(long = "layer", value_name = "SPEC")]
+    layers: Vec<composite::NoiseLayer>,
+
+    /// Compute the heights with an expression instead of `noise`, like
+    /// `noise(x * 4, y * 4) * 0.5 + ridge(x * 2, y * 2)`. `x` and `y` go from 0 to 1 along the
+    /// longest side of the terrain and the result is remapped to the amplitude. The expression
+    /// can use numbers, `pi`, `+`, `-`, `*`, `/`, `^`, parentheses, the noise functions
+    /// `noise(x, y)`, `fbm(x, y[, octaves])` and `ridge(x, y[, octaves])`, which are the same as
+    /// `--noise` with `seed`, `octaves`, `gain` and `lacunarity`, and `sin`, `cos`, `abs`,
+    /// `sqrt`, `exp`, `floor`, `min`, `max`, `pow` and `clamp`. Values that aren't finite, like
+    /// the square root of a negative number, count as 0.
+    #[arg(long, allow_hyphen_values = true)]
+    expr: Option<expr::Expr>,
+
     #[arg(long, default_value = "0.5")]
     lacunarity: f32,
 
@@ -75,7 +346,10 @@ pub struct RandomConfig {
 
 #[derive(Parser)]
 pub struct HeightmapConfig {
-    /// Input grayscale heightmap.
+    /// Input grayscale heightmap, 16 bit images keep their full precision. 32 bit float images,
+    /// like OpenEXR ones, are remapped so that their lowest value sits on the base and the
+    /// highest one at `amplitude` above it. Transparent pixels, if any, are left out of the
+    /// footprint of the model.
     #[arg()]
     grayscale_heightmap: PathBuf,
 
@@ -92,6 +366,56 @@ pub struct HeightmapConfig {
     /// performed via a Gaussian blur.
     #[arg(short, long, default_value = "0.3")]
     smoothness: f32,
+
+    /// The pixel value that corresponds to the sea level. When specified, darker pixels end up
+    /// below the reference plane at z = 0 and the base is lowered accordingly so that the model
+    /// includes the seabed. The value is always on the 8 bit scale, even for 16 bit images.
+    #[arg(long = "sea-level-pixel")]
+    sea_level_pixel: Option<u8>,
+
+    /// How the elevation is stored in the pixels of the image. The elevations of RGB encoded
+    /// tiles are remapped like the ones of a GeoTIFF.
+    #[arg(
+        long,
+        value_enum,
+        default_value = "grayscale",
+        conflicts_with_all = ["geotiff", "raw", "xyz"]
+    )]
+    encoding: encoding::Encoding,
+
+    /// Read the input as a single band GeoTIFF DEM, like the ones exported by GDAL, instead of an
+    /// image. The elevations are remapped so that the lowest one sits on the base and the
+    /// highest one at `amplitude` above it, and the nodata samples are left out of the footprint
+    /// of the model.
+    #[arg(long)]
+    geotiff: bool,
+
+    /// Read the input as a flat buffer of little endian f32 with the given size, as
+    /// `WIDTHxHEIGHT`, in row major order from the top left corner. The heights are remapped
+    /// like the ones of a GeoTIFF and the non finite ones are left out of the footprint.
+    #[arg(long, value_name = "WIDTHxHEIGHT", conflicts_with = "geotiff")]
+    raw: Option<raw::RawSize>,
+
+    /// Read the input as a CSV or XYZ text file of `x,y,z` samples, like survey and lidar
+    /// exports, and regrid them onto the lattice. The heights are remapped like the ones of a
+    /// GeoTIFF and the vertices that no sample reaches are left out of the footprint.
+    #[arg(long, conflicts_with_all = ["geotiff", "raw"])]
+    xyz: bool,
+
+    /// The distance between two adjacent vertices of the lattice of `xyz`, in the units of the
+    /// samples. Defaults to the distance between the columns of gridded samples and to the one
+    /// that gives about as many vertices as samples for scattered ones.
+    #[arg(long = "xyz-spacing", requires = "xyz")]
+    xyz_spacing: Option<f64>,
+
+    /// How to regrid the samples of `xyz` onto the lattice.
+    #[arg(long, value_enum, default_value = "nearest")]
+    regrid: xyz::Regrid,
+
+    /// Snap the heights to this many evenly spaced levels and output the terrain as a stack of
+    /// flat sheets with vertical risers, like a model made of laser cut acrylic sheets.
+    #[arg(long = "quantize-levels", value_parser = clap::value_parser!(u16).range(2..))]
+    quantize_levels: Option<u16>,
 }
 
 #[derive(Debug)]
@@ -100,7 +424,11 @@ pub struct Terrain {
     width: usize,
     depth: usize,
     amplitude: f32,
+    floor: f32,
     generator: TerrainGenerator,
+    georef: Option<dem::GeoReference>,
+    mask: Option<Vec<f32>>,
+    levels: Option<Vec<f32>>,
 }
 
 #[derive(Debug, Clone)]
@@ -108,76 +436,325 @@ pub enum TerrainGenerator {
     Noise { seed: u64 },
     Dual { parent_seed: u64 },
     Heightmap,
+    Dem,
+    Tectonic { seed: u64 },
+    Fluvial { seed: u64 },
+    Dunes { seed: u64 },
+    Gpx,
+}
+
+impl TerrainGenerator {
+    /// The seed the terrain was generated from, if any.
+    pub fn seed(&self) -> Option<u64> {
+        match *self {
+            TerrainGenerator::Noise { seed }
+            | TerrainGenerator::Dual { parent_seed: seed }
+            | TerrainGenerator::Tectonic { seed }
+            | TerrainGenerator::Fluvial { seed }
+            | TerrainGenerator::Dunes { seed } => Some(seed),
+            TerrainGenerator::Heightmap | TerrainGenerator::Dem | TerrainGenerator::Gpx => None,
+        }
+    }
 }
 
 impl Terrain {
-    pub fn generate(
-        RandomConfig {
-            amplitude,
-            base_thickness,
-            depth,
-            frequency,
-            gain,
-            lacunarity,
-            octaves,
-            seed,
-            width,
-        }: &RandomConfig,
-    ) -> Self {
-        let seed = seed.unwrap_or_else(|| {
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("time drift detected, aborting")
-                .as_secs()
-        });
+    pub fn generate(cfg: &RandomConfig) -> Self {
+        let seed = seed_or_now(cfg.seed);
         let noise_seed = Pcg32::seed_from_u64(seed).gen::<i32>();
 
-        let width = usize::from(*width);
-        let depth = usize::from(*depth);
+        let width = usize::from(cfg.width);
+        let depth = usize::from(cfg.depth);
+
+        let sample = |cfg: &RandomConfig, seed: i32| {
+            if cfg.warp > 0.0 {
+                warped_noise_grid(cfg, seed, width, depth)
+            } else {
+                noise_grid(cfg, seed, (0.0, 0.0), width, depth)
+            }
+        };
+
+        let mut heights = sample(cfg, noise_seed);
 
-        let mut noise_config = NoiseBuilder::fbm_2d(width, depth);
-        noise_config
-            .with_octaves(*octaves)
-            .with_freq(*frequency)
-            .with_gain(*gain)
-            .with_lacunarity(*lacunarity)
-            .with_seed(noise_seed);
+        if !cfg.layers.is_empty() {
+            // combine the layers in [0, 1] so that their amplitudes are relative to each other
+            grid::normalize(&mut heights);
 
-        let heights = noise_config.generate_scaled(*base_thickness, base_thickness + *amplitude);
+            for (i, layer) in cfg.layers.iter().enumerate() {
+                let mut layer_cfg = cfg.clone();
+                layer_cfg.noise = layer.noise;
+                layer_cfg.expr = None;
+                layer_cfg.frequency = layer.frequency.unwrap_or(cfg.frequency);
+                layer_cfg.octaves = layer.octaves.unwrap_or(cfg.octaves);
+                layer_cfg.gain = layer.gain.unwrap_or(cfg.gain);
+                layer_cfg.lacunarity = layer.lacunarity.unwrap_or(cfg.lacunarity);
+
+                let layer_seed = layer
+                    .seed
+                    .unwrap_or_else(|| seed.wrapping_add(i as u64 + 1));
+                let mut values = sample(&layer_cfg, Pcg32::seed_from_u64(layer_seed).gen());
+                grid::normalize(&mut values);
+
+                for (h, v) in heights.iter_mut().zip(values) {
+                    *h = layer.op.apply(*h, v * layer.amplitude);
+                }
+            }
+        }
+
+        // same as the scaling of simdnoise, so that the heights don't depend on how the noise
+        // was sampled
+        let (lo, hi) = heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+        let multiplier = if hi > lo {
+            cfg.amplitude / (hi - lo)
+        } else {
+            0.0
+        };
+        let offset = cfg.base_thickness - lo * multiplier;
+        for h in &mut heights {
+            *h = *h * multiplier + offset;
+        }
 
         Terrain {
             depth,
             heights,
             width,
-            amplitude: *amplitude,
+            amplitude: cfg.amplitude,
+            floor: 0.0,
             generator: TerrainGenerator::Noise { seed },
+            georef: None,
+            mask: None,
+            levels: None,
+        }
+    }
+
+    pub fn from_tectonic(cfg: &tectonic::TectonicConfig) -> Self {
+        let seed = seed_or_now(cfg.seed);
+
+        let heights = tectonic::simulate(cfg, seed)
+            .into_iter()
+            .map(|h| cfg.base_thickness + h * cfg.amplitude)
+            .collect();
+
+        Terrain {
+            depth: usize::from(cfg.depth),
+            heights,
+            width: usize::from(cfg.width),
+            amplitude: cfg.amplitude,
+            floor: 0.0,
+            generator: TerrainGenerator::Tectonic { seed },
+            georef: None,
+            mask: None,
+            levels: None,
         }
     }
 
+    pub fn from_fluvial(cfg: &fluvial::FluvialConfig) -> Self {
+        let seed = seed_or_now(cfg.seed);
+
+        let heights = fluvial::simulate(cfg, seed)
+            .into_iter()
+            .map(|h| cfg.base_thickness + h * cfg.amplitude)
+            .collect();
+
+        Terrain {
+            depth: usize::from(cfg.depth),
+            heights,
+            width: usize::from(cfg.width),
+            amplitude: cfg.amplitude,
+            floor: 0.0,
+            generator: TerrainGenerator::Fluvial { seed },
+            georef: None,
+            mask: None,
+            levels: None,
+        }
+    }
+
+    pub fn from_dunes(cfg: &dunes::DunesConfig) -> Self {
+        let seed = seed_or_now(cfg.seed);
+
+        let heights = dunes::simulate(cfg, seed)
+            .into_iter()
+            .map(|h| cfg.base_thickness + h * cfg.amplitude)
+            .collect();
+
+        Terrain {
+            depth: usize::from(cfg.depth),
+            heights,
+            width: usize::from(cfg.width),
+            amplitude: cfg.amplitude,
+            floor: 0.0,
+            generator: TerrainGenerator::Dunes { seed },
+            georef: None,
+            mask: None,
+            levels: None,
+        }
+    }
+
+    pub fn from_gpx(cfg: &gpx::GpxConfig) -> io::Result<Self> {
+        let profile = gpx::profile(cfg)?;
+        let depth = usize::from(cfg.ribbon_width).max(1) + 1;
+
+        // every row across the ribbon is the same profile
+        let heights = (0..depth)
+            .flat_map(|_| profile.iter())
+            .map(|h| cfg.base_thickness + h * cfg.amplitude)
+            .collect();
+
+        Ok(Terrain {
+            depth,
+            heights,
+            width: profile.len(),
+            amplitude: cfg.amplitude,
+            floor: 0.0,
+            generator: TerrainGenerator::Gpx,
+            georef: None,
+            mask: None,
+            levels: None,
+        })
+    }
+
     pub fn from_heightmap(
         HeightmapConfig {
             amplitude,
             base_thickness,
+            encoding,
+            geotiff,
             grayscale_heightmap,
+            quantize_levels,
+            raw,
+            regrid,
+            sea_level_pixel,
             smoothness,
+            xyz,
+            xyz_spacing,
         }: &HeightmapConfig,
     ) -> image::ImageResult<Self> {
         use std::convert::TryFrom;
 
-        let img = image::open(grayscale_heightmap)?.to_luma8();
-        let img = image::imageops::blur(&img, *smoothness);
+        // the samples in [0, 1] and the opacities, if any, in row major order from the top left
+        // corner
+        let raster = match raw {
+            Some(size) => Some(raw::read_raw(grayscale_heightmap, *size)?),
+            None if *geotiff => Some(geotiff::read_raster(grayscale_heightmap)?),
+            None if *xyz => Some(xyz::read_xyz(grayscale_heightmap, *xyz_spacing, *regrid)?),
+            None if *encoding != encoding::Encoding::Grayscale => {
+                Some(encoding::read_encoded(grayscale_heightmap, *encoding)?)
+            }
+            None => None,
+        };
+
+        let (width, depth, samples, opacities) = if let Some(raster) = raster {
+            let samples = grid::normalize_samples(&raster.samples).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} has no valid samples", grayscale_heightmap.display()),
+                )
+            })?;
+            let samples = grid::blur(samples, raster.width, raster.depth, *smoothness);
+
+            let opacities = if raster.samples.iter().any(Option::is_none) {
+                Some(
+                    raster
+                        .samples
+                        .iter()
+                        .map(|s| if s.is_some() { 1.0 } else { 0.0 })
+                        .collect::<Vec<_>>(),
+                )
+            } else {
+                None
+            };
+
+            (raster.width, raster.depth, samples, opacities)
+        } else {
+            let img = image::open(grayscale_heightmap)?;
+            let alpha = img.to_luma_alpha8();
+
+            // keep the full precision of 16 bit images, converting them to 8 bit would leave
+            // visible terraces on smooth slopes
+            let high_precision = !matches!(
+                img.color(),
+                ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8
+            );
+            let floating_point = matches!(img.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+            let samples: Vec<f32> = if floating_point {
+                // float images, like OpenEXR displacement maps, aren't limited to [0, 1] so
+                // they're remapped from their own range to keep all of it
+                let (width, depth) = (img.width() as usize, img.height() as usize);
+                let luma = img
+                    .to_rgb32f()
+                    .pixels()
+                    .map(|p| {
+                        let [r, g, b] = p.0;
+                        let l = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                        Some(l).filter(|l| l.is_finite())
+                    })
+                    .collect::<Vec<_>>();
+                let samples = grid::normalize_samples(&luma).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} has no valid pixels", grayscale_heightmap.display()),
+                    )
+                })?;
+                grid::blur(samples, width, depth, *smoothness)
+            } else if high_precision {
+                let img = image::imageops::blur(&img.to_luma16(), *smoothness);
+                img.pixels()
+                    .map(|p| f32::from(p.0[0]) / f32::from(u16::MAX))
+                    .collect()
+            } else {
+                let img = image::imageops::blur(&img.to_luma8(), *smoothness);
+                img.pixels().map(|p| f32::from(p.0[0]) / 255.0).collect()
+            };
+
+            let opacities = if alpha.pixels().any(|p| p.0[1] < 255) {
+                Some(alpha.pixels().map(|p| f32::from(p.0[1]) / 255.0).collect())
+            } else {
+                None
+            };
 
-        let (width, depth) = img.dimensions();
-        let width = usize::try_from(width).unwrap();
-        let depth = usize::try_from(depth).unwrap();
+            let (width, depth) = alpha.dimensions();
+            (
+                usize::try_from(width).unwrap(),
+                usize::try_from(depth).unwrap(),
+                samples,
+                opacities,
+            )
+        };
+
+        let sea_level = sea_level_pixel.map_or(0.0, |p| f32::from(p) / 255.0 * amplitude);
+        let (base_thickness, floor) = match sea_level_pixel {
+            None => (*base_thickness, 0.0),
+            Some(_) => (0.0, -sea_level - base_thickness),
+        };
 
         let mut heights = vec![0.0; depth * width];
-        for (x, y, p) in img.enumerate_pixels() {
-            let x = usize::try_from(x).unwrap();
-            let y = usize::try_from(y).unwrap();
-            let i = (depth - 1 - y) * width + x;
+        for (i, s) in samples.into_iter().enumerate() {
+            let (x, y) = (i % width, i / width);
 
-            heights[i] = base_thickness + f32::from(p.0[0]) / 255.0 * amplitude;
+            heights[(depth - 1 - y) * width + x] = base_thickness + s * amplitude - sea_level;
+        }
+
+        // transparent pixels are outside the footprint, the mask crosses zero halfway between
+        // fully transparent and fully opaque
+        let mask = opacities.map(|opacities| {
+            let mut mask = vec![0.0; depth * width];
+            for (i, o) in opacities.into_iter().enumerate() {
+                let (x, y) = (i % width, i / width);
+
+                mask[(depth - 1 - y) * width + x] = o - 0.5;
+            }
+            mask
+        });
+
+        let levels =
+            quantize_levels.map(|n| quantize::levels(base_thickness - sea_level, *amplitude, n));
+        if let Some(levels) = &levels {
+            for h in &mut heights {
+                *h = quantize::snap(*h, levels);
+            }
         }
 
         Ok(Terrain {
@@ -185,7 +762,97 @@ impl Terrain {
             heights,
             width,
             amplitude: *amplitude,
+            floor,
             generator: TerrainGenerator::Heightmap,
+            georef: None,
+            mask,
+            levels,
+        })
+    }
+
+    pub fn from_dem(
+        dem::DemConfig {
+            base_thickness,
+            bbox,
+            exaggeration,
+            resolution,
+            tiles,
+        }: &dem::DemConfig,
+    ) -> io::Result<Self> {
+        let mosaic = dem::HgtMosaic::open(tiles)?;
+
+        let bbox = bbox.unwrap_or_else(|| mosaic.bbox());
+        if !mosaic.covers(&bbox) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the bbox is not completely covered by the tiles",
+            ));
+        }
+
+        let cell_size = resolution.unwrap_or((mosaic.spacing() * dem::METERS_PER_DEGREE) as f32);
+        let (extent_x, extent_y) = dem::GeoReference::extent(&bbox);
+        let width = (extent_x / f64::from(cell_size)) as usize + 1;
+        let depth = (extent_y / f64::from(cell_size)) as usize + 1;
+
+        let mut georef = dem::GeoReference {
+            bbox,
+            center_lon: (bbox.min_lon + bbox.max_lon) / 2.0,
+            center_lat: (bbox.min_lat + bbox.max_lat) / 2.0,
+            cell_size,
+            exaggeration: *exaggeration,
+            min_elevation: 0.0,
+        };
+
+        let elevations = (0..depth)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (lon, lat) = georef.lon_lat_at(x as f64, y as f64);
+                mosaic.elevation_at(lon, lat)
+            })
+            .collect::<Vec<_>>();
+
+        let (min_elevation, max_elevation) = elevations
+            .iter()
+            .flatten()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &e| {
+                (lo.min(e), hi.max(e))
+            });
+        if min_elevation > max_elevation {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the tiles have only voids in the bbox",
+            ));
+        }
+        georef.min_elevation = min_elevation;
+
+        let scale = georef.vertical_scale();
+        let heights = elevations
+            .iter()
+            .map(|e| base_thickness + e.map_or(0.0, |e| e - min_elevation) * scale)
+            .collect();
+
+        // voids are outside the footprint like the nodata samples of GeoTIFFs
+        let mask = if elevations.iter().any(Option::is_none) {
+            Some(
+                elevations
+                    .iter()
+                    .map(|e| if e.is_some() { 0.5 } else { -0.5 })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        Ok(Terrain {
+            depth,
+            heights,
+            width,
+            amplitude: (max_elevation - min_elevation) * scale,
+            floor: 0.0,
+            generator: TerrainGenerator::Dem,
+            georef: Some(georef),
+            mask,
+            levels: None,
         })
     }
 
@@ -199,11 +866,29 @@ impl Terrain {
             TerrainGenerator::Noise { seed } => TerrainGenerator::Dual { parent_seed: seed },
             TerrainGenerator::Dual { parent_seed } => TerrainGenerator::Noise { seed: parent_seed },
             TerrainGenerator::Heightmap => TerrainGenerator::Heightmap,
+            TerrainGenerator::Dem => TerrainGenerator::Dem,
+            TerrainGenerator::Tectonic { seed } => TerrainGenerator::Tectonic { seed },
+            TerrainGenerator::Fluvial { seed } => TerrainGenerator::Fluvial { seed },
+            TerrainGenerator::Dunes { seed } => TerrainGenerator::Dunes { seed },
+            TerrainGenerator::Gpx => TerrainGenerator::Gpx,
         };
 
+        let mask = self.mask.as_ref().map(|mask| {
+            self.positions_by_depth()
+                .map(|(y, x)| mask[self.index_of(self.width - 1 - x, y)])
+                .collect()
+        });
+
+        let levels = self
+            .levels
+            .as_ref()
+            .map(|levels| levels.iter().rev().map(|z| self.amplitude - z).collect());
+
         Terrain {
             heights,
             generator,
+            mask,
+            levels,
             ..*self
         }
     }
@@ -212,6 +897,34 @@ impl Terrain {
         self.heights[y * self.width + x]
     }
 
+    /// The gradient of the terrain at the given position computed via central differences, or one
+    /// sided differences on the borders.
+    pub fn gradient_at(&self, x: usize, y: usize) -> (f32, f32) {
+        let x0 = x.saturating_sub(1);
+        let x1 = (x + 1).min(self.width - 1);
+        let y0 = y.saturating_sub(1);
+        let y1 = (y + 1).min(self.depth - 1);
+
+        let dx = if x1 > x0 {
+            (self.height_at(x1, y) - self.height_at(x0, y)) / (x1 - x0) as f32
+        } else {
+            0.0
+        };
+        let dy = if y1 > y0 {
+            (self.height_at(x, y1) - self.height_at(x, y0)) / (y1 - y0) as f32
+        } else {
+            0.0
+        };
+
+        (dx, dy)
+    }
+
+    /// The magnitude of the gradient of the terrain at the given position.
+    pub fn slope_at(&self, x: usize, y: usize) -> f32 {
+        let (dx, dy) = self.gradient_at(x, y);
+        dx.hypot(dy)
+    }
+
     pub fn iter_by_depth(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
         self.heights
             .iter()
@@ -223,6 +936,15 @@ impl Terrain {
         (0..self.depth).flat_map(move |y| (0..self.width).map(move |x| (y, x)))
     }
 
+    /// Replace every height with the result of calling `f` with its position and current value.
+    pub fn map_heights(&mut self, mut f: impl FnMut(usize, usize, f32) -> f32) {
+        let width = self.width;
+
+        for (i, h) in self.heights.iter_mut().enumerate() {
+            *h = f(i % width, i / width, *h);
+        }
+    }
+
     pub fn index_of(&self, x: usize, y: usize) -> usize {
         y * self.width + x
     }
@@ -239,128 +961,671 @@ impl Terrain {
         self.amplitude
     }
 
+    /// The height of the plane the solid sits on.
+    pub fn floor(&self) -> f32 {
+        self.floor
+    }
+
     pub fn generator(&self) -> &TerrainGenerator {
         &self.generator
     }
+
+    pub fn georef(&self) -> Option<&dem::GeoReference> {
+        self.georef.as_ref()
+    }
+
+    /// The value of the footprint mask at the given position, positive values are inside the
+    /// footprint. Terrains without a mask are considered to be entirely inside.
+    pub fn mask_at(&self, x: usize, y: usize) -> f32 {
+        self.mask
+            .as_ref()
+            .map_or(1.0, |mask| mask[self.index_of(x, y)])
+    }
+
+    /// The discrete heights of a quantized terrain, sorted from the lowest.
+    pub fn levels(&self) -> Option<&[f32]> {
+        self.levels.as_deref()
+    }
+
+    pub fn has_mask(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    /// Restrict the footprint of the terrain to where the given mask is positive, intersecting it
+    /// with the current mask if any.
+    pub fn apply_mask(&mut self, mask: Vec<f32>) {
+        self.mask = Some(match self.mask.take() {
+            None => mask,
+            Some(current) => current.iter().zip(mask).map(|(a, b)| a.min(b)).collect(),
+        });
+    }
+
+    /// The real world distance in meters between two adjacent vertices, if known.
+    pub fn cell_size(&self) -> Option<f32> {
+        self.georef.map(|g| g.cell_size)
+    }
 }
 
 fn main() -> image::ImageResult<()> {
     let opt = App::parse();
+    let reporter = Reporter::new(opt.json_output);
+    let outputs = Outputs::new(&opt.output, opt.no_clobber, opt.compress);
+
+    if opt.compress && opt.format() == Format::ThreeMf {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "3mf files are already compressed and can't be combined with compress",
+        )
+        .into());
+    }
+    let filters = filter::pipeline(&opt, &filter::FilterRegistry::with_builtins())?;
 
-    let terrain = match opt.command {
-        Command::Random(cfg) => Terrain::generate(&cfg),
-        Command::Heightmap(cfg) => Terrain::from_heightmap(&cfg)?,
+    let mut terrain = match &opt.command {
+        Command::Layers(cfg) => return layers::write_layers(cfg, &opt, &outputs, &reporter),
+        Command::Random(cfg) => Terrain::generate(cfg),
+        Command::Heightmap(cfg) => Terrain::from_heightmap(cfg)?,
+        Command::Dem(cfg) => Terrain::from_dem(cfg)?,
+        Command::Tectonic(cfg) => Terrain::from_tectonic(cfg),
+        Command::Fluvial(cfg) => Terrain::from_fluvial(cfg),
+        Command::Dunes(cfg) => Terrain::from_dunes(cfg),
+        Command::Gpx(cfg) => Terrain::from_gpx(cfg)?,
     };
 
-    let mut f = BufWriter::new(File::create(&opt.output)?);
-    dump(&mut f, &terrain, true)?;
+    for filter in &filters {
+        filter.apply(&mut terrain)?;
+    }
+
+    plan_outputs(&terrain, &opt, &outputs)?;
+
+    let path = write_terrain(output::TERRAIN, &terrain, &opt, &outputs, &reporter)?;
+
+    if let Some(georef) = terrain.georef() {
+        let sidecar_path = outputs.companion(&path, "json")?;
+        let mut f = BufWriter::new(File::create(&sidecar_path)?);
+        georef.dump_sidecar(&mut f, terrain.width(), terrain.depth())?;
+        f.flush()?;
+        reporter.file(&sidecar_path, "json");
+    }
+
+    if let Some(splat_path) = &opt.splat.bake_splat {
+        outputs.claim(splat_path)?;
+        splat::bake_splat(&terrain, &opt.splat).save(splat_path)?;
+        reporter.file(splat_path, "png");
+    }
+
+    if let Some(render_path) = &opt.render.render {
+        outputs.claim(render_path)?;
+        render::render(&terrain, &opt.render).save(render_path)?;
+        reporter.file(render_path, "png");
+    }
 
     if opt.dual {
         let dual = terrain.dual();
 
-        let dual_output = opt.output.with_file_name(format!(
-            "{}-dual.{}",
-            opt.output
-                .file_stem()
-                .map_or_else(|| "terrain".into(), |oss| oss.to_string_lossy()),
-            opt.output
-                .extension()
-                .map_or_else(|| "obj".into(), |oss| oss.to_string_lossy()),
+        write_terrain("dual", &dual, &opt, &outputs, &reporter)?;
+    }
+
+    Ok(())
+}
+
+/// Plan the paths of all the files written by a run, so that collisions and files that can't be
+/// replaced are reported before writing anything.
+fn plan_outputs(terrain: &Terrain, opt: &App, outputs: &Outputs) -> io::Result<()> {
+    let format = opt.format();
+    let ext = format.extension();
+
+    let path = outputs.path_of(output::TERRAIN, None, ext);
+    if terrain.georef().is_some() {
+        outputs.plan(&outputs.companion_of(&path, "json"))?;
+    }
+
+    let mut kinds = vec![output::TERRAIN];
+    if opt.dual {
+        kinds.push("dual");
+    }
+
+    for kind in kinds {
+        let path = outputs.path_of(kind, None, ext);
+        outputs.plan(&path)?;
+
+        if format == Format::Npy {
+            continue;
+        }
+
+        match opt.submerged {
+            Submerged::Merged => {}
+            Submerged::Colored if format == Format::Obj => {
+                outputs.plan(&outputs.companion_of(&path, "mtl"))?;
+            }
+            Submerged::Colored => {}
+            Submerged::Separate => {
+                outputs.plan(&outputs.path_of(&seabed_kind(kind), None, ext))?;
+            }
+        }
+    }
+
+    for path in opt.splat.bake_splat.iter().chain(&opt.render.render) {
+        outputs.plan(path)?;
+    }
+
+    Ok(())
+}
+
+/// The kind of the output of the seabed of the output of the given kind.
+fn seabed_kind(kind: &str) -> String {
+    if kind == output::TERRAIN {
+        "seabed".to_string()
+    } else {
+        format!("{}-seabed", kind)
+    }
+}
+
+/// Write the terrain as an output of the given kind, returning the path of the main file.
+fn write_terrain(
+    kind: &str,
+    terrain: &Terrain,
+    opt: &App,
+    outputs: &Outputs,
+    reporter: &Reporter,
+) -> io::Result<PathBuf> {
+    let format = opt.format();
+
+    if format == Format::Npy {
+        let path = outputs.path(kind, None, format.extension())?;
+        let mut w = outputs.create(&path)?;
+        npy::dump_npy(&mut w, terrain)?;
+        w.finish()?;
+
+        let samples = terrain.width() * terrain.depth();
+        reporter.terrain(&path, "npy", terrain, samples, 0);
+        return Ok(path);
+    }
+
+    let (mesh, seabed) = terrain_meshes(terrain, opt)?;
+
+    let path = outputs.path(kind, None, format.extension())?;
+    write_mesh(&path, &mesh, terrain, opt, outputs, reporter)?;
+
+    if let Some(seabed) = seabed {
+        let seabed_path = outputs.path(&seabed_kind(kind), None, format.extension())?;
+        write_mesh(&seabed_path, &seabed, terrain, opt, outputs, reporter)?;
+    }
+
+    Ok(path)
+}
+
+/// Convert the terrain into the mesh of the solid to write according to the options, along with
+/// the mesh of the seabed when it's output separately.
+fn terrain_meshes(terrain: &Terrain, opt: &App) -> io::Result<(Mesh, Option<Mesh>)> {
+    if let Some(levels) = terrain.levels() {
+        if opt.sea_level.is_some()
+            || opt.submerged != Submerged::Merged
+            || opt.engrave.is_enabled()
+            || opt.mount.is_enabled()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "quantize-levels can't be combined with sea-level, submerged, engravings or \
+                 mounting features",
+            ));
+        }
+
+        return Ok((quantize::stepped_mesh(terrain, levels), None));
+    }
+
+    if terrain.has_mask() && (opt.engrave.is_enabled() || opt.mount.is_enabled()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "masks, including the alpha channel of a heightmap, can't be combined with engravings \
+             or mounting features",
+        ));
+    }
+
+    if terrain.has_mask() && opt.submerged == Submerged::Colored {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "masks, including the nodata samples of a raster, can't be combined with submerged \
+             colored",
         ));
+    }
+
+    if opt.submerged == Submerged::Separate && (opt.engrave.is_enabled() || opt.mount.is_enabled())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "engravings and mounting features can't be combined with separate submerged output",
+        ));
+    }
+
+    let engraving = if opt.engrave.is_enabled() {
+        Some(engrave::engrave_front_wall(terrain, &opt.engrave)?)
+    } else {
+        None
+    };
+    let engraving = engraving.as_ref();
+
+    let mounting = if opt.mount.is_enabled() {
+        Some(mount::mount_base(terrain, &opt.mount)?)
+    } else {
+        None
+    };
+    let mounting = mounting.as_ref();
+
+    let meshes = match (opt.submerged, opt.sea_level) {
+        (Submerged::Merged, None) if !terrain.has_mask() => (
+            terrain_mesh(terrain, true, false, engraving, mounting),
+            None,
+        ),
+        (Submerged::Merged, sea_level) => (
+            coastline::cropped_solid(terrain, sea_level, Side::Above),
+            None,
+        ),
+        (Submerged::Colored, _) => (terrain_mesh(terrain, true, true, engraving, mounting), None),
+        (Submerged::Separate, sea_level) => {
+            let sea_level = Some(sea_level.unwrap_or(0.0));
+
+            (
+                coastline::cropped_solid(terrain, sea_level, Side::Above),
+                Some(coastline::cropped_solid(terrain, sea_level, Side::Below)),
+            )
+        }
+    };
+
+    Ok(meshes)
+}
+
+/// Write the mesh generated from `terrain` to the given path in the mesh format of the options,
+/// along with its material library if it uses materials and the format needs one.
+pub fn write_mesh(
+    path: &Path,
+    mesh: &Mesh,
+    terrain: &Terrain,
+    opt: &App,
+    outputs: &Outputs,
+    reporter: &Reporter,
+) -> io::Result<()> {
+    let triangulated;
+    let mesh = if opt.triangulate {
+        triangulated = mesh.triangulated();
+        &triangulated
+    } else {
+        mesh
+    };
 
-        let mut f = BufWriter::new(File::create(dual_output)?);
-        dump(&mut f, &dual, true)?;
+    let format = opt.format();
+
+    if format == Format::ThreeMf {
+        // 3mf packages are zip files, they're always compressed
+        threemf::dump_3mf(
+            BufWriter::new(File::create(path)?),
+            mesh,
+            &opt.threemf,
+            opt.precision,
+        )?;
+
+        let faces = mesh.triangles().count();
+        reporter.terrain(path, "3mf", terrain, mesh.vertices.len(), faces);
+        return Ok(());
     }
 
+    let mut w = outputs.create(path)?;
+    let faces = match format {
+        Format::Obj => {
+            let mtllib = if mesh.has_materials() {
+                let mtl_path = outputs.companion(path, "mtl")?;
+                let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+                mesh::dump_mtl(&mut mtl)?;
+                mtl.flush()?;
+                reporter.file(&mtl_path, "mtl");
+
+                mtl_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            } else {
+                None
+            };
+
+            mesh::dump_obj(&mut w, mesh, mtllib.as_deref(), opt.precision)?;
+            mesh.faces.len()
+        }
+        Format::Stl => {
+            stl::dump_stl(&mut w, mesh)?;
+            mesh.triangles().count()
+        }
+        Format::Off => {
+            off::dump_off(&mut w, mesh, opt.precision)?;
+            mesh.faces.len()
+        }
+        Format::ThreeMf | Format::Npy => unreachable!("{:?} is handled above", format),
+    };
+    w.finish()?;
+
+    reporter.terrain(
+        path,
+        format.extension(),
+        terrain,
+        mesh.vertices.len(),
+        faces,
+    );
     Ok(())
 }
 
-pub fn dump(w: &mut impl Write, terrain: &Terrain, support: bool) -> io::Result<()> {
-    writeln!(
-        w,
-        r#"# generated by terrain-mesh <https://github.com/danieledapo/terrain-mesh>
-# {}{}
-o terrain"#,
-        env::args().collect::<Vec<_>>().join(" "),
-        match terrain.generator {
-            TerrainGenerator::Noise { seed } | TerrainGenerator::Dual { parent_seed: seed } => {
-                format!("\n# seed: {}", seed)
-            }
-            TerrainGenerator::Heightmap => String::new(),
+/// Sample the noise of the config on a grid of `width` x `depth` points whose top left corner is
+/// at the given position, in cells. The values are not scaled.
+fn noise_grid(
+    cfg: &RandomConfig,
+    seed: i32,
+    (x, y): (f32, f32),
+    width: usize,
+    depth: usize,
+) -> Vec<f32> {
+    if let Some(expr) = &cfg.expr {
+        let noise = expr::Simplex {
+            seed,
+            octaves: cfg.octaves,
+            gain: cfg.gain,
+            lacunarity: cfg.lacunarity,
+        };
+        let scale = (cfg.width.max(cfg.depth).max(2) - 1) as f32;
+
+        return (0..depth)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let h = expr.eval(&expr::Context {
+                    x: (x + i as f32) / scale,
+                    y: (y + j as f32) / scale,
+                    noise: &noise,
+                });
+                if h.is_finite() {
+                    h
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+    }
+
+    let (values, _, _) = match cfg.noise {
+        Noise::Fbm | Noise::Billow => NoiseBuilder::fbm_2d_offset(x, width, y, depth)
+            .with_octaves(cfg.octaves)
+            .with_freq(cfg.frequency)
+            .with_gain(cfg.gain)
+            .with_lacunarity(cfg.lacunarity)
+            .with_seed(seed)
+            .generate(),
+        Noise::Turbulence => NoiseBuilder::turbulence_2d_offset(x, width, y, depth)
+            .with_octaves(cfg.octaves)
+            .with_freq(cfg.frequency)
+            .with_gain(cfg.gain)
+            .with_lacunarity(cfg.lacunarity)
+            .with_seed(seed)
+            .generate(),
+        Noise::Simplex => NoiseBuilder::gradient_2d_offset(x, width, y, depth)
+            .with_freq(cfg.frequency)
+            .with_seed(seed)
+            .generate(),
+        Noise::Cellular => NoiseBuilder::cellular_2d_offset(x, width, y, depth)
+            .with_freq(cfg.frequency)
+            .with_distance_function(match cfg.distance_function {
+                DistanceFunction::Euclidean => CellDistanceFunction::Euclidean,
+                DistanceFunction::Manhattan => CellDistanceFunction::Manhattan,
+                DistanceFunction::Natural => CellDistanceFunction::Natural,
+            })
+            .with_return_type(CellReturnType::Distance)
+            .with_jitter(cfg.jitter)
+            .with_seed(seed)
+            .generate(),
+    };
+
+    match cfg.noise {
+        Noise::Billow => values.into_iter().map(f32::abs).collect(),
+        _ => values,
+    }
+}
+
+/// Sample the noise of the config at positions displaced by up to `warp` cells by two smooth
+/// noise fields, one per axis. The values are not scaled.
+fn warped_noise_grid(cfg: &RandomConfig, seed: i32, width: usize, depth: usize) -> Vec<f32> {
+    // the displaced positions can fall outside of the terrain, sample a margin around it too
+    let pad = cfg.warp.ceil() as usize + 1;
+    let (padded_width, padded_depth) = (width + 2 * pad, depth + 2 * pad);
+    let base = noise_grid(
+        cfg,
+        seed,
+        (-(pad as f32), -(pad as f32)),
+        padded_width,
+        padded_depth,
+    );
+
+    let displacement = |seed: i32| {
+        let (mut field, lo, hi) = NoiseBuilder::gradient_2d(width, depth)
+            .with_freq(cfg.warp_frequency)
+            .with_seed(seed)
+            .generate();
+
+        // in [-1, 1] so that no position moves farther than `warp`
+        let range = (hi - lo).max(f32::EPSILON);
+        for v in &mut field {
+            *v = (*v - lo) / range * 2.0 - 1.0;
         }
-    )?;
+        field
+    };
+    let dx = displacement(seed.wrapping_add(1));
+    let dy = displacement(seed.wrapping_add(2));
+
+    (0..depth)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let i = y * width + x;
+            let sx = (x + pad) as f32 + cfg.warp * dx[i];
+            let sy = (y + pad) as f32 + cfg.warp * dy[i];
+
+            let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+            let (tx, ty) = (sx - x0 as f32, sy - y0 as f32);
+            let at = |x: usize, y: usize| base[y * padded_width + x];
+
+            let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+            let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+            top * (1.0 - ty) + bottom * ty
+        })
+        .collect()
+}
+
+/// Use the given seed or derive one from the current time.
+fn seed_or_now(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time drift detected, aborting")
+            .as_secs()
+    })
+}
+
+/// Convert the terrain into a mesh made of one quad per cell. With `support` the mesh is closed
+/// by walls down to the floor and a bottom face, possibly with the given engravings on the front
+/// wall and the mounting features on the bottom. With `colored` every face is assigned a
+/// material depending on whether it's submerged or not.
+pub fn terrain_mesh(
+    terrain: &Terrain,
+    support: bool,
+    colored: bool,
+    engraving: Option<&Engraving>,
+    mounting: Option<&Mounting>,
+) -> Mesh {
+    let mut mesh = Mesh::new(mesh_comments(terrain));
+
+    if let Some(length) = engraving.and_then(|e| e.scale_bar_length) {
+        mesh.comments.push(format!("scale bar: {} m", length));
+    }
 
     for (y, x, z) in terrain.iter_by_depth() {
-        writeln!(w, "v {} {} {}", x, y, z)?;
+        mesh.add_vertex([x as f32, y as f32, z]);
     }
 
+    let depth = terrain.depth();
+    let width = terrain.width();
+    let oi = width * depth;
+    let ei = oi + width * depth;
+    let mi = ei + engraving.map_or(0, |e| e.vertices.len());
+
     if support {
         for (y, x) in terrain.positions_by_depth() {
-            writeln!(w, "v {} {} 0", x, y)?;
+            mesh.add_vertex([x as f32, y as f32, terrain.floor()]);
+        }
+
+        for v in engraving.iter().flat_map(|e| &e.vertices) {
+            mesh.add_vertex(*v);
+        }
+
+        for v in mounting.iter().flat_map(|m| &m.vertices) {
+            mesh.add_vertex(*v);
         }
     }
 
-    let depth = terrain.depth();
-    let width = terrain.width();
     for y in 0..depth.saturating_sub(1) {
         for x in 0..width.saturating_sub(1) {
-            let i = 1 + terrain.index_of(x, y);
-            let j = 1 + terrain.index_of(x, y + 1);
-            writeln!(w, "f {} {} {} {}", i, i + 1, j + 1, j)?;
+            let material = if colored {
+                let submerged = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+                    .iter()
+                    .all(|&(x, y)| terrain.height_at(x, y) < 0.0);
+
+                Some(if submerged {
+                    Material::Seabed
+                } else {
+                    Material::Land
+                })
+            } else {
+                None
+            };
+
+            let i = terrain.index_of(x, y);
+            let j = terrain.index_of(x, y + 1);
+            mesh.add_face(vec![i, i + 1, j + 1, j], material);
         }
     }
 
-    if support {
-        let oi = width * depth + 1;
-        writeln!(
-            w,
-            "f {} {} {} {}",
-            oi,
-            oi + terrain.index_of(0, depth - 1),
-            oi + terrain.index_of(width - 1, depth - 1),
-            oi + terrain.index_of(width - 1, 0),
-        )?;
+    if !support {
+        return mesh;
+    }
+
+    let base = if colored { Some(Material::Base) } else { None };
+
+    if mounting.is_none() {
+        mesh.add_face(
+            vec![
+                oi,
+                oi + terrain.index_of(0, depth - 1),
+                oi + terrain.index_of(width - 1, depth - 1),
+                oi + terrain.index_of(width - 1, 0),
+            ],
+            base,
+        );
+    }
 
-        for y in 0..depth.saturating_sub(1) {
-            writeln!(
-                w,
-                "f {} {} {} {}",
-                oi + terrain.index_of(0, y + 1),
-                oi + terrain.index_of(0, y),
-                1 + terrain.index_of(0, y),
-                1 + terrain.index_of(0, y + 1),
-            )?;
-
-            writeln!(
-                w,
-                "f {} {} {} {}",
+    let wall_vertex = |v: &WallVertex| match *v {
+        WallVertex::Top(x) => terrain.index_of(x, 0),
+        WallVertex::Floor(x) => oi + terrain.index_of(x, 0),
+        WallVertex::Extra(i) => ei + i,
+    };
+
+    // the first cells of the side walls must be split where the engraved front wall is split
+    let (left_border, right_border) = match engraving {
+        Some(e) => (
+            e.left_border.iter().map(wall_vertex).collect(),
+            e.right_border.iter().rev().map(wall_vertex).collect(),
+        ),
+        None => (vec![], vec![]),
+    };
+
+    for y in 0..depth.saturating_sub(1) {
+        let (left_border, right_border) = if y == 0 {
+            (&left_border[..], &right_border[..])
+        } else {
+            (&[][..], &[][..])
+        };
+
+        mesh.add_face(
+            [
+                &[oi + terrain.index_of(0, y + 1), oi + terrain.index_of(0, y)],
+                left_border,
+                &[terrain.index_of(0, y), terrain.index_of(0, y + 1)],
+            ]
+            .concat(),
+            base,
+        );
+
+        let mut right = [
+            &[
                 oi + terrain.index_of(width - 1, y),
                 oi + terrain.index_of(width - 1, y + 1),
-                1 + terrain.index_of(width - 1, y + 1),
-                1 + terrain.index_of(width - 1, y),
-            )?;
+                terrain.index_of(width - 1, y + 1),
+                terrain.index_of(width - 1, y),
+            ],
+            right_border,
+        ]
+        .concat();
+
+        // faces are triangulated as a fan around their first vertex, which must not lie on the
+        // split edge
+        if !right_border.is_empty() {
+            right.rotate_left(1);
         }
+        mesh.add_face(right, base);
+    }
 
-        for x in 0..width.saturating_sub(1) {
-            writeln!(
-                w,
-                "f {} {} {} {}",
-                oi + terrain.index_of(x, 0),
-                oi + terrain.index_of(x + 1, 0),
-                1 + terrain.index_of(x + 1, 0),
-                1 + terrain.index_of(x, 0),
-            )?;
-
-            writeln!(
-                w,
-                "f {} {} {} {}",
+    for x in 0..width.saturating_sub(1) {
+        if engraving.is_none() {
+            mesh.add_face(
+                vec![
+                    oi + terrain.index_of(x, 0),
+                    oi + terrain.index_of(x + 1, 0),
+                    terrain.index_of(x + 1, 0),
+                    terrain.index_of(x, 0),
+                ],
+                base,
+            );
+        }
+
+        mesh.add_face(
+            vec![
                 oi + terrain.index_of(x + 1, depth - 1),
                 oi + terrain.index_of(x, depth - 1),
-                1 + terrain.index_of(x, depth - 1),
-                1 + terrain.index_of(x + 1, depth - 1),
-            )?;
-        }
+                terrain.index_of(x, depth - 1),
+                terrain.index_of(x + 1, depth - 1),
+            ],
+            base,
+        );
     }
 
-    Ok(())
+    for face in engraving.iter().flat_map(|e| &e.faces) {
+        mesh.add_face(face.iter().map(wall_vertex).collect(), base);
+    }
+
+    for face in mounting.iter().flat_map(|m| &m.faces) {
+        let vertices = face
+            .iter()
+            .map(|v| match *v {
+                BaseVertex::Floor(x, y) => oi + terrain.index_of(x, y),
+                BaseVertex::Extra(i) => mi + i,
+            })
+            .collect();
+        mesh.add_face(vertices, base);
+    }
+
+    mesh
+}
+
+/// The comments that describe how the terrain was generated, to be written in the header of the
+/// output files.
+pub fn mesh_comments(terrain: &Terrain) -> Vec<String> {
+    let mut comments = vec![
+        "generated by terrain-mesh <https://github.com/danieledapo/terrain-mesh>".to_string(),
+        env::args().collect::<Vec<_>>().join(" "),
+    ];
+
+    if let Some(seed) = terrain.generator.seed() {
+        comments.push(format!("seed: {}", seed));
+    }
+
+    comments
 }
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+/// A polygonal mesh, the common representation all the sources of geometry convert into and
+/// all the exporters write.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    /// Free form lines that describe how the mesh was generated, for the formats that support
+    /// comments.
+    pub comments: Vec<String>,
+
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<Face>,
+}
+
+/// A convex polygon whose vertices are counter clockwise when seen from outside the solid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+    pub vertices: Vec<usize>,
+    pub material: Option<Material>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Material {
+    Land,
+    Seabed,
+    Base,
+}
+
+impl Material {
+    pub const ALL: [Material; 3] = [Material::Land, Material::Seabed, Material::Base];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Material::Land => "land",
+            Material::Seabed => "seabed",
+            Material::Base => "base",
+        }
+    }
+
+    /// The diffuse color as rgb components in [0, 1].
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            Material::Land => [0.38, 0.55, 0.24],
+            Material::Seabed => [0.16, 0.35, 0.60],
+            Material::Base => [0.50, 0.50, 0.50],
+        }
+    }
+}
+
+impl Mesh {
+    pub fn new(comments: Vec<String>) -> Self {
+        Mesh {
+            comments,
+            ..Mesh::default()
+        }
+    }
+
+    /// Add a vertex and return its index.
+    pub fn add_vertex(&mut self, v: [f32; 3]) -> usize {
+        self.vertices.push(v);
+        self.vertices.len() - 1
+    }
+
+    pub fn add_face(&mut self, vertices: Vec<usize>, material: Option<Material>) {
+        self.faces.push(Face { vertices, material });
+    }
+
+    /// Move all the vertices and the faces of `other` into this mesh, its comments are dropped.
+    pub fn append(&mut self, other: Mesh) {
+        let offset = self.vertices.len();
+
+        self.vertices.extend(other.vertices);
+        self.faces.extend(other.faces.into_iter().map(|mut f| {
+            for v in &mut f.vertices {
+                *v += offset;
+            }
+            f
+        }));
+    }
+
+    pub fn has_materials(&self) -> bool {
+        self.faces.iter().any(|f| f.material.is_some())
+    }
+
+    /// Split every face into a fan of triangles.
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.faces.iter().flat_map(|f| fan(&f.vertices))
+    }
+
+    /// The same mesh with every face split into a fan of triangles that keep its material.
+    pub fn triangulated(&self) -> Mesh {
+        let faces = self
+            .faces
+            .iter()
+            .flat_map(|f| {
+                fan(&f.vertices).map(move |tri| Face {
+                    vertices: tri.to_vec(),
+                    material: f.material,
+                })
+            })
+            .collect();
+
+        Mesh {
+            comments: self.comments.clone(),
+            vertices: self.vertices.clone(),
+            faces,
+        }
+    }
+}
+
+/// Split the polygon into a fan of triangles around its first vertex, which preserves its
+/// orientation since faces are convex.
+pub fn fan(polygon: &[usize]) -> impl Iterator<Item = [usize; 3]> + '_ {
+    (1..polygon.len().saturating_sub(1)).map(move |i| [polygon[0], polygon[i], polygon[i + 1]])
+}
+
+/// A coordinate formatted with at most the given number of decimals, or with the shortest
+/// representation that reads back to the same value if there's no precision.
+pub struct Coord(pub f32, pub Option<u8>);
+
+impl fmt::Display for Coord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = match self.1 {
+            None => return write!(f, "{}", self.0),
+            Some(precision) => precision,
+        };
+
+        // integer arithmetic is much faster than formatting the float with a fixed precision
+        let scale = 10_i64.pow(u32::from(precision));
+        let n = (f64::from(self.0) * scale as f64).round() as i64;
+        if n < 0 {
+            f.write_str("-")?;
+        }
+
+        let (int, mut frac) = (n.abs() / scale, n.abs() % scale);
+        write!(f, "{}", int)?;
+        if frac == 0 {
+            return Ok(());
+        }
+
+        let mut digits = usize::from(precision);
+        while frac % 10 == 0 {
+            frac /= 10;
+            digits -= 1;
+        }
+        write!(f, ".{:0width$}", frac, width = digits)
+    }
+}
+
+/// Write the mesh as a Wavefront obj, referencing the given material library if any. The
+/// coordinates are written with the given precision, see `Coord`.
+pub fn dump_obj(
+    w: &mut impl Write,
+    mesh: &Mesh,
+    mtllib: Option<&str>,
+    precision: Option<u8>,
+) -> io::Result<()> {
+    for comment in &mesh.comments {
+        writeln!(w, "# {}", comment)?;
+    }
+    writeln!(w, "o terrain")?;
+
+    if let Some(mtllib) = mtllib {
+        writeln!(w, "mtllib {}", mtllib)?;
+    }
+
+    for &[x, y, z] in &mesh.vertices {
+        writeln!(
+            w,
+            "v {} {} {}",
+            Coord(x, precision),
+            Coord(y, precision),
+            Coord(z, precision)
+        )?;
+    }
+
+    let mut material = None;
+    for face in &mesh.faces {
+        if let Some(m) = face.material.filter(|_| mtllib.is_some()) {
+            if material != Some(m) {
+                writeln!(w, "usemtl {}", m.name())?;
+                material = Some(m);
+            }
+        }
+
+        write!(w, "f")?;
+        for v in &face.vertices {
+            write!(w, " {}", v + 1)?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/// Write the material library that goes with the meshes that use materials.
+pub fn dump_mtl(w: &mut impl Write) -> io::Result<()> {
+    for (i, m) in Material::ALL.iter().enumerate() {
+        if i > 0 {
+            writeln!(w)?;
+        }
+
+        let [r, g, b] = m.color();
+        writeln!(w, "newmtl {}", m.name())?;
+        writeln!(w, "Kd {:.2} {:.2} {:.2}", r, g, b)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,309 @@
+use std::f32::consts::PI;
+use std::io;
+use std::str::FromStr;
+
+use clap::Parser;
+
+use crate::triangulate::{triangulate, Point};
+use crate::Terrain;
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[derive(Parser)]
+pub struct MountConfig {
+    /// Position of a cylindrical pocket for a magnet in the bottom of the base, as `x,y` in grid
+    /// coordinates. Can be repeated.
+    #[arg(long, conflicts_with_all = ["sea_level", "mask"])]
+    pub magnet: Vec<Position>,
+
+    #[arg(long = "magnet-diameter", default_value = "6.0")]
+    pub magnet_diameter: f32,
+
+    #[arg(long = "magnet-depth", default_value = "2.0")]
+    pub magnet_depth: f32,
+
+    /// Position of a countersunk blind hole in the bottom of the base for a screw or a threaded
+    /// insert, as `x,y` in grid coordinates. Can be repeated.
+    #[arg(long, conflicts_with_all = ["sea_level", "mask"])]
+    pub screw: Vec<Position>,
+
+    #[arg(long = "screw-diameter", default_value = "3.0")]
+    pub screw_diameter: f32,
+
+    #[arg(long = "screw-head-diameter", default_value = "6.0")]
+    pub screw_head_diameter: f32,
+
+    #[arg(long = "screw-depth", default_value = "6.0")]
+    pub screw_depth: f32,
+
+    /// Position of a keyhole hanger in the bottom of the base, as `x,y` in grid coordinates. The
+    /// slot extends from the given position towards the back of the terrain. Can be repeated.
+    #[arg(long, conflicts_with_all = ["sea_level", "mask"])]
+    pub keyhole: Vec<Position>,
+
+    /// The diameter of the hole the head of the screw goes through.
+    #[arg(long = "keyhole-head-diameter", default_value = "8.0")]
+    pub keyhole_head_diameter: f32,
+
+    /// The width of the slot the shank of the screw slides along.
+    #[arg(long = "keyhole-shank-diameter", default_value = "4.0")]
+    pub keyhole_shank_diameter: f32,
+
+    #[arg(long = "keyhole-length", default_value = "10.0")]
+    pub keyhole_length: f32,
+
+    /// The depth of the narrow slot, that is the thickness of the lip that holds the screw head.
+    #[arg(long = "keyhole-depth", default_value = "2.0")]
+    pub keyhole_depth: f32,
+
+    /// The height of the wide channel above the slot where the screw head slides.
+    #[arg(long = "keyhole-undercut", default_value = "2.5")]
+    pub keyhole_undercut: f32,
+}
+
+/// A position on the base in grid coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A vertex of the bottom of the base, either one of the floor vertices of the terrain or an
+/// extra one the pockets need.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaseVertex {
+    Floor(usize, usize),
+    Extra(usize),
+}
+
+/// The faces that replace the bottom of the base.
+#[derive(Debug)]
+pub struct Mounting {
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<Vec<BaseVertex>>,
+}
+
+/// A blind pocket described by a stack of outlines with the same number of corresponding points,
+/// from the opening on the bottom face upwards.
+struct Pocket {
+    rings: Vec<(Vec<Point>, f32)>,
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid coordinate: {}", e))?;
+
+        match coords[..] {
+            [x, y] => Ok(Position { x, y }),
+            _ => Err("expected x,y".to_string()),
+        }
+    }
+}
+
+impl MountConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.magnet.is_empty() || !self.screw.is_empty() || !self.keyhole.is_empty()
+    }
+}
+
+impl Pocket {
+    fn depth(&self) -> f32 {
+        self.rings.last().map_or(0.0, |(_, z)| *z) - self.rings[0].1
+    }
+
+    fn bounds(&self) -> (Point, Point) {
+        self.rings.iter().flat_map(|(ring, _)| ring).fold(
+            (
+                (f32::INFINITY, f32::INFINITY),
+                (f32::NEG_INFINITY, f32::NEG_INFINITY),
+            ),
+            |(lo, hi), p| {
+                (
+                    (lo.0.min(p.0), lo.1.min(p.1)),
+                    (hi.0.max(p.0), hi.1.max(p.1)),
+                )
+            },
+        )
+    }
+}
+
+/// Cut the configured pockets into the bottom of the base of the terrain.
+pub fn mount_base(terrain: &Terrain, cfg: &MountConfig) -> io::Result<Mounting> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+    let floor = terrain.floor();
+    let mut pockets = vec![];
+
+    for p in &cfg.magnet {
+        let c = circle(*p, cfg.magnet_diameter / 2.0);
+        pockets.push(Pocket {
+            rings: vec![(c.clone(), floor), (c, floor + cfg.magnet_depth)],
+        });
+    }
+
+    for p in &cfg.screw {
+        let countersink = (cfg.screw_head_diameter - cfg.screw_diameter).max(0.0) / 2.0;
+        if countersink >= cfg.screw_depth {
+            return Err(invalid(
+                "the screw hole is shallower than its countersink".into(),
+            ));
+        }
+
+        let shank = circle(*p, cfg.screw_diameter / 2.0);
+        pockets.push(Pocket {
+            rings: vec![
+                (circle(*p, cfg.screw_head_diameter / 2.0), floor),
+                (shank.clone(), floor + countersink),
+                (shank, floor + cfg.screw_depth),
+            ],
+        });
+    }
+
+    for p in &cfg.keyhole {
+        let r = cfg.keyhole_head_diameter / 2.0;
+        let s = cfg.keyhole_shank_diameter / 2.0;
+        if s >= r || cfg.keyhole_length <= r {
+            return Err(invalid(
+                "the keyhole shank must be narrower than the head and the slot longer than it"
+                    .into(),
+            ));
+        }
+
+        let slot = keyhole(*p, r, s, cfg.keyhole_length);
+        let channel = keyhole(*p, r, r, cfg.keyhole_length);
+        let z = floor + cfg.keyhole_depth;
+
+        pockets.push(Pocket {
+            rings: vec![
+                (slot.clone(), floor),
+                (slot, z),
+                (channel.clone(), z),
+                (channel, z + cfg.keyhole_undercut),
+            ],
+        });
+    }
+
+    let (max_x, max_y) = ((terrain.width() - 1) as f32, (terrain.depth() - 1) as f32);
+    for (i, pocket) in pockets.iter().enumerate() {
+        let (lo, hi) = pocket.bounds();
+        if lo.0 <= 0.0 || lo.1 <= 0.0 || hi.0 >= max_x || hi.1 >= max_y {
+            return Err(invalid(format!(
+                "the mounting feature at {:.1},{:.1} doesn't fit in the base",
+                lo.0, lo.1
+            )));
+        }
+
+        let lowest = (lo.1.floor() as usize..=hi.1.ceil() as usize)
+            .flat_map(|y| (lo.0.floor() as usize..=hi.0.ceil() as usize).map(move |x| (x, y)))
+            .map(|(x, y)| terrain.height_at(x, y))
+            .fold(f32::INFINITY, f32::min);
+        if floor + pocket.depth() >= lowest {
+            return Err(invalid(format!(
+                "the mounting feature at {:.1},{:.1} pierces the terrain, try to increase \
+                 base-thickness",
+                lo.0, lo.1
+            )));
+        }
+
+        for other in &pockets[..i] {
+            let (olo, ohi) = other.bounds();
+            if lo.0 < ohi.0 && olo.0 < hi.0 && lo.1 < ohi.1 && olo.1 < hi.1 {
+                return Err(invalid("mounting features must not overlap".into()));
+            }
+        }
+    }
+
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    let mut openings = vec![];
+    let mut opening_ids = vec![];
+
+    for pocket in &pockets {
+        let n = pocket.rings[0].0.len();
+
+        let mut ring_ids = vec![];
+        for (ring, z) in &pocket.rings {
+            ring_ids.push(vertices.len());
+            vertices.extend(ring.iter().map(|p| [p.0, p.1, *z]));
+        }
+
+        for k in 0..ring_ids.len() - 1 {
+            let (lo, hi) = (ring_ids[k], ring_ids[k + 1]);
+
+            for i in 0..n {
+                let j = (i + 1) % n;
+                faces.push(vec![
+                    BaseVertex::Extra(lo + i),
+                    BaseVertex::Extra(hi + i),
+                    BaseVertex::Extra(hi + j),
+                    BaseVertex::Extra(lo + j),
+                ]);
+            }
+        }
+
+        let top = ring_ids[ring_ids.len() - 1];
+        faces.push((0..n).rev().map(|i| BaseVertex::Extra(top + i)).collect());
+
+        openings.push(pocket.rings[0].0.clone());
+        opening_ids.extend(ring_ids[0]..ring_ids[0] + n);
+    }
+
+    // use all the floor vertices along the border so that the bottom shares its edges with the
+    // walls
+    let (w, d) = (terrain.width() - 1, terrain.depth() - 1);
+    let border = (0..w)
+        .map(|x| (x, 0))
+        .chain((0..d).map(|y| (w, y)))
+        .chain((1..=w).rev().map(|x| (x, d)))
+        .chain((1..=d).rev().map(|y| (0, y)))
+        .collect::<Vec<_>>();
+    let outer = border
+        .iter()
+        .map(|&(x, y)| (x as f32, y as f32))
+        .collect::<Vec<_>>();
+
+    let base_vertex = |i: usize| match border.get(i) {
+        Some(&(x, y)) => BaseVertex::Floor(x, y),
+        None => BaseVertex::Extra(opening_ids[i - border.len()]),
+    };
+
+    for [a, b, c] in triangulate(&outer, &openings) {
+        faces.push(vec![base_vertex(c), base_vertex(b), base_vertex(a)]);
+    }
+
+    Ok(Mounting { vertices, faces })
+}
+
+fn circle(center: Position, r: f32) -> Vec<Point> {
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            let a = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+            (center.x + r * a.cos(), center.y + r * a.sin())
+        })
+        .collect()
+}
+
+/// The counter clockwise outline of a keyhole made of a circular head of radius `r` and a slot of
+/// half width `s` that extends for `length` towards +y. The number of points doesn't depend on
+/// the parameters so that outlines with different sizes correspond point by point.
+fn keyhole(center: Position, r: f32, s: f32, length: f32) -> Vec<Point> {
+    let alpha = (s / r).asin();
+    let head = (0..=CIRCLE_SEGMENTS).map(|i| {
+        let a = PI / 2.0 + alpha + i as f32 / CIRCLE_SEGMENTS as f32 * (2.0 * PI - 2.0 * alpha);
+        (center.x + r * a.cos(), center.y + r * a.sin())
+    });
+
+    let cap_segments = CIRCLE_SEGMENTS / 2;
+    let cap = (0..=cap_segments).map(|i| {
+        let a = i as f32 / cap_segments as f32 * PI;
+        (center.x + s * a.cos(), center.y + length + s * a.sin())
+    });
+
+    head.chain(cap).collect()
+}
@@ -0,0 +1,37 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::Write;
+
+use crate::Terrain;
+
+/// Write the heights of the given terrain as a float32 [npy][0] array with shape `(depth,
+/// width)`.
+///
+/// [0]: https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+pub fn dump_npy(w: &mut impl Write, terrain: &Terrain) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        terrain.depth(),
+        terrain.width()
+    );
+
+    // the magic string, the version and the header length take 10 bytes and the whole preamble
+    // must be terminated by a newline and be aligned to 64 bytes.
+    let preamble_len = 10 + header.len() + 1;
+    let padding = (64 - preamble_len % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let header_len = u16::try_from(header.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "npy header too long"))?;
+
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_all(&header_len.to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+
+    for (_, _, z) in terrain.iter_by_depth() {
+        w.write_all(&z.to_le_bytes())?;
+    }
+
+    Ok(())
+}
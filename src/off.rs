@@ -0,0 +1,37 @@
+use std::io;
+use std::io::Write;
+
+use crate::mesh::{Coord, Mesh};
+
+/// Write the mesh as an [OFF][0] file, keeping the faces as polygons. Materials are not
+/// supported and are ignored. The coordinates are written with the given precision, see `Coord`.
+///
+/// [0]: https://segeval.cs.princeton.edu/public/off_format.html
+pub fn dump_off(w: &mut impl Write, mesh: &Mesh, precision: Option<u8>) -> io::Result<()> {
+    writeln!(w, "OFF")?;
+    for comment in &mesh.comments {
+        writeln!(w, "# {}", comment)?;
+    }
+
+    writeln!(w, "{} {} 0", mesh.vertices.len(), mesh.faces.len())?;
+
+    for &[x, y, z] in &mesh.vertices {
+        writeln!(
+            w,
+            "{} {} {}",
+            Coord(x, precision),
+            Coord(y, precision),
+            Coord(z, precision)
+        )?;
+    }
+
+    for face in &mesh.faces {
+        write!(w, "{}", face.vertices.len())?;
+        for v in &face.vertices {
+            write!(w, " {}", v)?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
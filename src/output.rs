@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// The kind of the main output of a run.
+pub const TERRAIN: &str = "terrain";
+
+/// Hands out the paths of the files generated in a run from the output template, making sure no
+/// file is written twice and, if requested, that no existing file is replaced.
+///
+/// The paths can be planned before writing anything so that a run that would fail these checks
+/// fails without leaving some of its files behind.
+pub struct Outputs {
+    template: PathBuf,
+    no_clobber: bool,
+    compress: bool,
+    claimed: RefCell<HashSet<PathBuf>>,
+    planned: RefCell<HashSet<PathBuf>>,
+}
+
+/// A file being written, possibly through gzip.
+pub enum OutputWriter {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<File>>),
+}
+
+impl Outputs {
+    pub fn new(template: &Path, no_clobber: bool, compress: bool) -> Self {
+        Outputs {
+            template: template.to_path_buf(),
+            no_clobber,
+            compress,
+            claimed: RefCell::new(HashSet::new()),
+            planned: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// The path of an output of the given `kind`, `index` is used to tell apart the outputs of
+    /// the same kind like layers.
+    ///
+    /// The `{kind}`, `{index}` and `{ext}` placeholders in the template are replaced with the
+    /// given values, the index defaulting to 0. The kind, if it isn't the main one, and the index
+    /// are appended to the file stem when the template doesn't mention them, for example
+    /// `terrain.obj` becomes `terrain-dual.obj` and `terrain-layer-1.obj`. With compression the
+    /// path gets the `.gz` extension too.
+    pub fn path(&self, kind: &str, index: Option<usize>, ext: &str) -> io::Result<PathBuf> {
+        let path = self.path_of(kind, index, ext);
+        self.claim(&path)?;
+        Ok(path)
+    }
+
+    /// The path `path` would return, without claiming it.
+    pub fn path_of(&self, kind: &str, index: Option<usize>, ext: &str) -> PathBuf {
+        let template = self.template.to_string_lossy();
+
+        let path = PathBuf::from(
+            template
+                .replace("{kind}", kind)
+                .replace("{index}", &index.unwrap_or(0).to_string())
+                .replace("{ext}", ext),
+        );
+
+        let mut suffix = vec![];
+        if kind != TERRAIN && !template.contains("{kind}") {
+            suffix.push(kind.to_string());
+        }
+        if let Some(index) = index.filter(|_| !template.contains("{index}")) {
+            suffix.push(index.to_string());
+        }
+
+        let path = if suffix.is_empty() {
+            path
+        } else {
+            sibling_path(&path, &suffix.join("-"), ext)
+        };
+
+        if self.compress {
+            let mut gz = OsString::from(path);
+            gz.push(".gz");
+            PathBuf::from(gz)
+        } else {
+            path
+        }
+    }
+
+    /// The path of a file that accompanies the given output, like its material library, which
+    /// has the same name but a different extension. Companions are never compressed.
+    pub fn companion(&self, path: &Path, ext: &str) -> io::Result<PathBuf> {
+        let path = self.companion_of(path, ext);
+        self.claim(&path)?;
+        Ok(path)
+    }
+
+    /// The path `companion` would return, without claiming it.
+    pub fn companion_of(&self, path: &Path, ext: &str) -> PathBuf {
+        if self.compress {
+            path.with_extension("").with_extension(ext)
+        } else {
+            path.with_extension(ext)
+        }
+    }
+
+    /// Create the file of an output at the given path, compressing it if requested.
+    pub fn create(&self, path: &Path) -> io::Result<OutputWriter> {
+        let f = File::create(path)?;
+
+        // buffer before the encoder, compressing every single line is slow
+        Ok(if self.compress {
+            OutputWriter::Gzip(BufWriter::new(GzEncoder::new(f, Compression::default())))
+        } else {
+            OutputWriter::Plain(BufWriter::new(f))
+        })
+    }
+
+    /// Reserve the given path for an output of this run before writing any file. Claiming it
+    /// later succeeds once.
+    pub fn plan(&self, path: &Path) -> io::Result<()> {
+        self.reserve(path)?;
+        self.planned.borrow_mut().insert(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Reserve the given path for an output of this run, unless it was planned.
+    pub fn claim(&self, path: &Path) -> io::Result<()> {
+        if self.planned.borrow_mut().remove(path) {
+            return Ok(());
+        }
+
+        self.reserve(path)
+    }
+
+    fn reserve(&self, path: &Path) -> io::Result<()> {
+        if !self.claimed.borrow_mut().insert(path.to_path_buf()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} would be written by more than one output",
+                    path.display()
+                ),
+            ));
+        }
+
+        if self.no_clobber && path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, drop --no-clobber to replace it",
+                    path.display()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Build the path of a file that is related to the given one by appending `-{suffix}` to its
+/// file stem.
+fn sibling_path(path: &Path, suffix: &str, default_extension: &str) -> PathBuf {
+    path.with_file_name(format!(
+        "{}-{}.{}",
+        path.file_stem()
+            .map_or_else(|| "terrain".into(), |oss| oss.to_string_lossy()),
+        suffix,
+        path.extension()
+            .map_or_else(|| default_extension.into(), |oss| oss.to_string_lossy()),
+    ))
+}
+
+impl OutputWriter {
+    /// Flush all the data to the file, this must be called to terminate the gzip stream.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(mut w) => w.flush(),
+            OutputWriter::Gzip(w) => {
+                w.into_inner().map_err(|e| e.into_error())?.finish()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(w) => w.write(buf),
+            OutputWriter::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush(),
+            OutputWriter::Gzip(w) => w.flush(),
+        }
+    }
+}
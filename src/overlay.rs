@@ -0,0 +1,273 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+use serde_json::Value;
+
+use crate::Terrain;
+
+#[derive(Parser)]
+pub struct CarveConfig {
+    /// GeoJSON file whose LineString and MultiLineString geometries are carved into the terrain,
+    /// like roads or trails. Coordinates are longitude and latitude for georeferenced terrains
+    /// and grid coordinates otherwise.
+    #[arg(long)]
+    pub carve: Option<PathBuf>,
+
+    /// The width of the carved paths.
+    #[arg(long = "carve-width", default_value = "1.0")]
+    pub carve_width: f32,
+
+    /// How deep the paths are carved. Negative values raise the paths instead.
+    #[arg(
+        long = "carve-depth",
+        default_value = "0.5",
+        allow_hyphen_values = true
+    )]
+    pub carve_depth: f32,
+
+    /// The shape of the cross section of the carved paths.
+    #[arg(long = "carve-profile", value_enum, default_value = "round")]
+    pub carve_profile: Profile,
+}
+
+#[derive(Parser)]
+pub struct MaskConfig {
+    /// GeoJSON file whose Polygon and MultiPolygon geometries are used as a mask, in the same
+    /// coordinates as `carve`.
+    #[arg(long)]
+    pub mask: Option<PathBuf>,
+
+    /// What to do with the regions inside the mask polygons.
+    #[arg(long = "mask-mode", value_enum, default_value = "crop")]
+    pub mask_mode: MaskMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MaskMode {
+    /// Crop the model to the polygons, generating walls along their outlines.
+    Crop,
+
+    /// Flatten the terrain inside each polygon to its lowest point, like the surface of a lake.
+    Flatten,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Profile {
+    /// Constant depth across the whole width.
+    Flat,
+
+    /// Circular cross section.
+    Round,
+
+    /// Depth that decreases linearly towards the edges.
+    V,
+}
+
+/// A polyline in the grid coordinates of a terrain.
+pub type Polyline = Vec<(f32, f32)>;
+
+/// A single geometry made of one or more polylines, like a polygon and its holes.
+pub type Geometry = Vec<Polyline>;
+
+impl Profile {
+    /// The fraction of the depth at the given normalized distance `t` in [0, 1] from the center
+    /// of the path.
+    pub fn weight(self, t: f32) -> f32 {
+        match self {
+            Profile::Flat => 1.0,
+            Profile::Round => (1.0 - t * t).max(0.0).sqrt(),
+            Profile::V => 1.0 - t,
+        }
+    }
+}
+
+/// Load the GeoJSON file at the given path and return all the geometries of the given `kinds`
+/// converted to the grid coordinates of `terrain`. Multi geometries are split into their parts.
+pub fn load_geojson(path: &Path, terrain: &Terrain, kinds: &[&str]) -> io::Result<Vec<Geometry>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let data = fs::read_to_string(path)?;
+    let json: Value = serde_json::from_str(&data)
+        .map_err(|e| invalid(format!("{} is not valid json: {}", path.display(), e)))?;
+
+    let mut geometries = vec![];
+    collect_geometries(&json, kinds, &mut geometries)
+        .ok_or_else(|| invalid(format!("{} is not valid GeoJSON", path.display())))?;
+
+    let to_grid = |(x, y): (f64, f64)| match terrain.georef() {
+        Some(georef) => {
+            let (gx, gy) = georef.grid_at(x, y);
+            (gx as f32, gy as f32)
+        }
+        None => (x as f32, y as f32),
+    };
+
+    Ok(geometries
+        .into_iter()
+        .map(|geometry| {
+            geometry
+                .into_iter()
+                .map(|line| line.into_iter().map(to_grid).collect())
+                .collect()
+        })
+        .collect())
+}
+
+type Positions = Vec<(f64, f64)>;
+
+fn collect_geometries(json: &Value, kinds: &[&str], out: &mut Vec<Vec<Positions>>) -> Option<()> {
+    match json.get("type")?.as_str()? {
+        "FeatureCollection" => {
+            for f in json.get("features")?.as_array()? {
+                collect_geometries(f, kinds, out)?;
+            }
+        }
+        "Feature" => match json.get("geometry")? {
+            Value::Null => {}
+            geometry => collect_geometries(geometry, kinds, out)?,
+        },
+        "GeometryCollection" => {
+            for g in json.get("geometries")?.as_array()? {
+                collect_geometries(g, kinds, out)?;
+            }
+        }
+        kind if kinds.contains(&kind) => {
+            let coords = json.get("coordinates")?;
+
+            match kind {
+                "LineString" => out.push(vec![parse_positions(coords)?]),
+                "MultiLineString" => {
+                    for line in coords.as_array()? {
+                        out.push(vec![parse_positions(line)?]);
+                    }
+                }
+                "Polygon" => out.push(parse_rings(coords)?),
+                "MultiPolygon" => {
+                    for polygon in coords.as_array()? {
+                        out.push(parse_rings(polygon)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    Some(())
+}
+
+fn parse_rings(json: &Value) -> Option<Vec<Positions>> {
+    json.as_array()?.iter().map(parse_positions).collect()
+}
+
+fn parse_positions(json: &Value) -> Option<Positions> {
+    json.as_array()?
+        .iter()
+        .map(|p| Some((p.get(0)?.as_f64()?, p.get(1)?.as_f64()?)))
+        .collect()
+}
+
+/// Carve the given paths into the terrain, never going below the floor of the terrain.
+pub fn carve(terrain: &mut Terrain, paths: &[Geometry], cfg: &CarveConfig) {
+    let radius = cfg.carve_width / 2.0;
+    let floor = terrain.floor();
+
+    terrain.map_heights(|x, y, h| {
+        let p = (x as f32, y as f32);
+
+        let d = paths
+            .iter()
+            .flatten()
+            .flat_map(|path| path.windows(2))
+            .map(|seg| segment_distance(p, seg[0], seg[1]))
+            .fold(f32::INFINITY, f32::min);
+
+        if d >= radius {
+            return h;
+        }
+
+        (h - cfg.carve_depth * cfg.carve_profile.weight(d / radius)).max(floor)
+    });
+}
+
+/// Apply the given polygons to the terrain according to `mode`.
+pub fn apply_polygons(terrain: &mut Terrain, polygons: &[Geometry], mode: MaskMode) {
+    match mode {
+        MaskMode::Crop => {
+            let mask = terrain
+                .positions_by_depth()
+                .map(|(y, x)| {
+                    let p = (x as f32, y as f32);
+
+                    polygons
+                        .iter()
+                        .map(|polygon| signed_distance(p, polygon))
+                        .fold(f32::NEG_INFINITY, f32::max)
+                })
+                .collect();
+
+            terrain.apply_mask(mask);
+        }
+        MaskMode::Flatten => {
+            for polygon in polygons {
+                let inside = |x: usize, y: usize| contains(polygon, (x as f32, y as f32));
+
+                let lowest = terrain
+                    .iter_by_depth()
+                    .filter(|&(y, x, _)| inside(x, y))
+                    .map(|(_, _, h)| h)
+                    .fold(f32::INFINITY, f32::min);
+
+                terrain.map_heights(|x, y, h| if inside(x, y) { lowest } else { h });
+            }
+        }
+    }
+}
+
+/// Whether the point is inside the polygon made of the given rings according to the even-odd
+/// rule, so that inner rings are holes.
+fn contains(rings: &[Polyline], p: (f32, f32)) -> bool {
+    let mut inside = false;
+
+    for ring in rings {
+        for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+/// The distance from the point to the outline of the polygon, positive inside and negative
+/// outside.
+fn signed_distance(p: (f32, f32), rings: &[Polyline]) -> f32 {
+    let d = rings
+        .iter()
+        .flat_map(|ring| ring.iter().zip(ring.iter().cycle().skip(1)))
+        .map(|(a, b)| segment_distance(p, *a, *b))
+        .fold(f32::INFINITY, f32::min);
+
+    if contains(rings, p) {
+        d
+    } else {
+        -d
+    }
+}
+
+fn segment_distance(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let ab = (b.0 - a.0, b.1 - a.1);
+    let ap = (p.0 - a.0, p.1 - a.1);
+
+    let len2 = ab.0 * ab.0 + ab.1 * ab.1;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        ((ap.0 * ab.0 + ap.1 * ab.1) / len2).clamp(0.0, 1.0)
+    };
+
+    (ap.0 - ab.0 * t).hypot(ap.1 - ab.1 * t)
+}
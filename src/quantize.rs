@@ -0,0 +1,44 @@
+use crate::coastline::{self, Side};
+use crate::mesh::Mesh;
+use crate::{mesh_comments, Terrain};
+
+/// The `n` evenly spaced heights between `lo` and `lo + amplitude`.
+pub fn levels(lo: f32, amplitude: f32, n: u16) -> Vec<f32> {
+    (0..n)
+        .map(|i| lo + amplitude * f32::from(i) / f32::from(n - 1))
+        .collect()
+}
+
+/// Snap the height to the closest of the given sorted levels.
+pub fn snap(h: f32, levels: &[f32]) -> f32 {
+    levels
+        .iter()
+        .copied()
+        .min_by(|a, b| (a - h).abs().partial_cmp(&(b - h).abs()).unwrap())
+        .unwrap_or(h)
+}
+
+/// Build the terrain as a stack of flat sheets, one per level, like a model made of laser cut
+/// sheets glued on top of each other.
+///
+/// Each sheet covers the region of the terrain that is closer to its level or to one of the
+/// levels above it and goes from the level below to its own, so that the risers between two
+/// levels are perfectly vertical.
+pub fn stepped_mesh(terrain: &Terrain, levels: &[f32]) -> Mesh {
+    let mut mesh = Mesh::new(mesh_comments(terrain));
+
+    let mut bottom = terrain.floor();
+    for (i, &level) in levels.iter().enumerate() {
+        if level <= bottom {
+            continue;
+        }
+
+        let threshold = i.checked_sub(1).map(|below| (levels[below] + level) / 2.0);
+        let region = coastline::crop(terrain, threshold, Side::Above);
+        mesh.append(region.solid(Some(level), bottom));
+
+        bottom = level;
+    }
+
+    mesh
+}
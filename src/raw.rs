@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::grid::Raster;
+
+/// The size of a raw heightfield as `WIDTHxHEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawSize {
+    pub width: usize,
+    pub depth: usize,
+}
+
+impl FromStr for RawSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let dims = s
+            .split('x')
+            .map(|d| d.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid dimension: {}", e))?;
+
+        match dims[..] {
+            [width, depth] if width >= 2 && depth >= 2 => Ok(RawSize { width, depth }),
+            _ => Err("expected WIDTHxHEIGHT with both dimensions at least 2".to_string()),
+        }
+    }
+}
+
+/// Read a flat buffer of little endian `f32` in row major order from the top left corner, like
+/// the arrays dumped by simulation tools. Non finite samples are considered missing.
+pub fn read_raw(path: &Path, size: RawSize) -> io::Result<Raster> {
+    let data = fs::read(path)?;
+
+    let expected = size.width * size.depth * 4;
+    if data.len() != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} has {} bytes but a {}x{} grid of f32 needs {}",
+                path.display(),
+                data.len(),
+                size.width,
+                size.depth,
+                expected
+            ),
+        ));
+    }
+
+    let samples = data
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .map(|s| Some(s).filter(|s| s.is_finite()))
+        .collect();
+
+    Ok(Raster {
+        width: size.width,
+        depth: size.depth,
+        samples,
+    })
+}
@@ -0,0 +1,340 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::{Parser, ValueEnum};
+use image::{Rgba, RgbaImage};
+
+use crate::Terrain;
+
+/// The size of the margins around the legend bar.
+const LEGEND_MARGIN: u32 = 12;
+
+const LEGEND_BAR_WIDTH: u32 = 16;
+
+const LEGEND_TICKS: usize = 5;
+
+/// How much the glyphs of the labels are scaled up.
+const FONT_SCALE: u32 = 2;
+
+/// A tiny 3x5 pixel font, just enough to write the elevation labels.
+const GLYPHS: [(char, [&str; 5]); 14] = [
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", ".##", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", ".#.", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('m', ["...", "...", "###", "###", "#.#"]),
+    (' ', ["...", "...", "...", "...", "..."]),
+];
+
+#[derive(Parser)]
+pub struct RenderConfig {
+    /// Render a PNG image of the terrain seen from above, colored by elevation and hillshaded,
+    /// with a legend that maps the colors to the elevations. Elevations are in meters for
+    /// georeferenced terrains and in model units otherwise.
+    #[arg(long)]
+    pub render: Option<PathBuf>,
+
+    /// The color ramp used to tint the terrain by elevation.
+    #[arg(long = "color-ramp", value_enum, default_value = "hypsometric")]
+    pub color_ramp: ColorRamp,
+
+    /// Custom color ramp as a comma separated list of `position:#rrggbb` stops where positions
+    /// are relative elevations in [0, 1], like `0:#2b83ba,0.5:#ffffbf,1:#d7191c`. Overrides
+    /// `color-ramp`.
+    #[arg(long = "color-stops")]
+    pub color_stops: Option<ColorStops>,
+
+    /// How strong the hillshading is, 0 disables it.
+    #[arg(long, default_value = "0.6")]
+    pub hillshade: f32,
+
+    /// The number of pixels per cell of the terrain.
+    #[arg(long = "render-scale", default_value = "4")]
+    pub render_scale: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorRamp {
+    /// The classic green, yellow, brown and white of topographic maps.
+    Hypsometric,
+
+    /// Sandy and reddish colors for arid regions.
+    Desert,
+
+    /// The perceptually uniform viridis ramp.
+    Viridis,
+
+    /// From black to white.
+    Grayscale,
+}
+
+/// A color ramp made of stops sorted by position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStops(Vec<(f32, [u8; 3])>);
+
+impl FromStr for ColorStops {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stops = s
+            .split(',')
+            .map(|stop| {
+                let (t, color) = stop
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected position:#rrggbb, got {}", stop))?;
+                let t = t
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|e| format!("invalid position {}: {}", t, e))?;
+                if !(0.0..=1.0).contains(&t) {
+                    return Err(format!("the position {} is not in [0, 1]", t));
+                }
+
+                let hex = color.trim().trim_start_matches('#');
+                let rgb = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 6)
+                    .ok_or_else(|| format!("invalid color {}", color))?;
+
+                Ok((t, [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if stops.len() < 2 {
+            return Err("a color ramp needs at least two stops".to_string());
+        }
+
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Ok(ColorStops(stops))
+    }
+}
+
+impl ColorRamp {
+    pub fn stops(self) -> ColorStops {
+        let stops = match self {
+            ColorRamp::Hypsometric => vec![
+                (0.0, [58, 120, 74]),
+                (0.25, [136, 176, 97]),
+                (0.5, [231, 220, 150]),
+                (0.75, [164, 118, 76]),
+                (0.9, [180, 170, 160]),
+                (1.0, [250, 250, 250]),
+            ],
+            ColorRamp::Desert => vec![
+                (0.0, [240, 224, 180]),
+                (0.4, [222, 178, 120]),
+                (0.7, [190, 120, 80]),
+                (1.0, [120, 70, 50]),
+            ],
+            ColorRamp::Viridis => vec![
+                (0.0, [68, 1, 84]),
+                (0.25, [59, 82, 139]),
+                (0.5, [33, 145, 140]),
+                (0.75, [94, 201, 98]),
+                (1.0, [253, 231, 37]),
+            ],
+            ColorRamp::Grayscale => vec![(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+        };
+
+        ColorStops(stops)
+    }
+}
+
+impl ColorStops {
+    /// The color at the given relative elevation in [0, 1].
+    pub fn color_at(&self, t: f32) -> [f32; 3] {
+        let stops = &self.0;
+        let i = stops.iter().position(|s| s.0 > t).unwrap_or(stops.len());
+
+        let (a, b) = match i {
+            0 => (stops[0], stops[0]),
+            i if i == stops.len() => (stops[i - 1], stops[i - 1]),
+            i => (stops[i - 1], stops[i]),
+        };
+
+        let f = if b.0 > a.0 {
+            (t - a.0) / (b.0 - a.0)
+        } else {
+            0.0
+        };
+        let mut color = [0.0; 3];
+        for (c, (ca, cb)) in color.iter_mut().zip(a.1.iter().zip(&b.1)) {
+            *c = f32::from(*ca) + (f32::from(*cb) - f32::from(*ca)) * f;
+        }
+        color
+    }
+}
+
+/// Render the terrain seen from above tinted by elevation and hillshaded with a light from the
+/// north west, with the legend on the right. The rows of the image go from the back of the
+/// terrain to the front and the regions outside the footprint are transparent.
+pub fn render(terrain: &Terrain, cfg: &RenderConfig) -> RgbaImage {
+    let ramp = cfg
+        .color_stops
+        .clone()
+        .unwrap_or_else(|| cfg.color_ramp.stops());
+
+    let (min_z, max_z) = terrain
+        .iter_by_depth()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (_, _, z)| {
+            (lo.min(z), hi.max(z))
+        });
+    let range = (max_z - min_z).max(f32::EPSILON);
+
+    let light = {
+        let (azimuth, altitude) = (135_f32.to_radians(), 45_f32.to_radians());
+        [
+            azimuth.cos() * altitude.cos(),
+            azimuth.sin() * altitude.cos(),
+            altitude.sin(),
+        ]
+    };
+
+    let labels = legend_labels(terrain, min_z, max_z);
+    let legend_width = LEGEND_MARGIN * 2
+        + LEGEND_BAR_WIDTH
+        + 7
+        + labels.iter().map(|l| l.len() as u32).max().unwrap_or(0) * 4 * FONT_SCALE;
+
+    let scale = cfg.render_scale.max(1);
+    let map_width = (terrain.width() as u32 - 1) * scale + 1;
+    let map_depth = (terrain.depth() as u32 - 1) * scale + 1;
+    let mut img = RgbaImage::from_pixel(
+        map_width + legend_width,
+        map_depth.max(LEGEND_TICKS as u32 * 20),
+        Rgba([255, 255, 255, 255]),
+    );
+
+    for py in 0..map_depth {
+        for px in 0..map_width {
+            let fx = px as f32 / scale as f32;
+            let fy = (map_depth - 1 - py) as f32 / scale as f32;
+
+            if sample(terrain, fx, fy, |x, y| terrain.mask_at(x, y)) <= 0.0 {
+                img.put_pixel(px, py, Rgba([0, 0, 0, 0]));
+                continue;
+            }
+
+            let z = sample(terrain, fx, fy, |x, y| terrain.height_at(x, y));
+            let nx = -sample(terrain, fx, fy, |x, y| terrain.gradient_at(x, y).0);
+            let ny = -sample(terrain, fx, fy, |x, y| terrain.gradient_at(x, y).1);
+            let len = (nx * nx + ny * ny + 1.0).sqrt();
+            let shade = ((nx * light[0] + ny * light[1] + light[2]) / len).max(0.0);
+
+            let k = 1.0 - cfg.hillshade + cfg.hillshade * shade / light[2];
+            let c = ramp.color_at((z - min_z) / range);
+            img.put_pixel(
+                px,
+                py,
+                Rgba([
+                    (c[0] * k).clamp(0.0, 255.0) as u8,
+                    (c[1] * k).clamp(0.0, 255.0) as u8,
+                    (c[2] * k).clamp(0.0, 255.0) as u8,
+                    255,
+                ]),
+            );
+        }
+    }
+
+    draw_legend(&mut img, map_width, &ramp, &labels);
+
+    img
+}
+
+/// The labels of the ticks of the legend from the lowest elevation to the highest.
+fn legend_labels(terrain: &Terrain, min_z: f32, max_z: f32) -> Vec<String> {
+    // elevations are in meters for georeferenced terrains
+    let (unit, to_elevation): (_, Box<dyn Fn(f32) -> f32>) = match terrain.georef() {
+        Some(georef) => {
+            let (min_elevation, scale) = (georef.min_elevation, georef.vertical_scale());
+            (" m", Box::new(move |z| min_elevation + (z - min_z) / scale))
+        }
+        None => ("", Box::new(|z| z)),
+    };
+    let decimals = if to_elevation(max_z) - to_elevation(min_z) >= 10.0 {
+        0
+    } else {
+        1
+    };
+
+    (0..LEGEND_TICKS)
+        .map(|i| {
+            let t = i as f32 / (LEGEND_TICKS - 1) as f32;
+            let elevation = to_elevation(min_z + (max_z - min_z) * t);
+            format!("{:.*}{}", decimals, elevation, unit)
+        })
+        .collect()
+}
+
+fn draw_legend(img: &mut RgbaImage, left: u32, ramp: &ColorStops, labels: &[String]) {
+    let bar_x = left + LEGEND_MARGIN;
+    let bar_top = LEGEND_MARGIN;
+    let bar_bottom = img.height().saturating_sub(LEGEND_MARGIN).max(bar_top + 1);
+    let bar_height = (bar_bottom - bar_top) as f32;
+
+    for py in bar_top..=bar_bottom {
+        let c = ramp.color_at(1.0 - (py - bar_top) as f32 / bar_height);
+        for px in bar_x..bar_x + LEGEND_BAR_WIDTH {
+            img.put_pixel(px, py, Rgba([c[0] as u8, c[1] as u8, c[2] as u8, 255]));
+        }
+    }
+
+    let black = Rgba([0, 0, 0, 255]);
+    for (i, label) in labels.iter().enumerate() {
+        let t = i as f32 / (labels.len() - 1) as f32;
+        let py = bar_bottom - (t * bar_height).round() as u32;
+
+        for px in bar_x + LEGEND_BAR_WIDTH..bar_x + LEGEND_BAR_WIDTH + 4 {
+            img.put_pixel(px, py, black);
+        }
+
+        let ty = py.saturating_sub(5 * FONT_SCALE / 2);
+        draw_text(img, bar_x + LEGEND_BAR_WIDTH + 7, ty, label, black);
+    }
+}
+
+fn draw_text(img: &mut RgbaImage, x: u32, y: u32, text: &str, color: Rgba<u8>) {
+    for (i, ch) in text.chars().enumerate() {
+        let glyph = match GLYPHS.iter().find(|(c, _)| *c == ch) {
+            Some((_, glyph)) => glyph,
+            None => continue,
+        };
+
+        let gx = x + i as u32 * 4 * FONT_SCALE;
+        for (r, row) in glyph.iter().enumerate() {
+            for (c, _) in row.chars().enumerate().filter(|(_, p)| *p == '#') {
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let px = gx + c as u32 * FONT_SCALE + dx;
+                        let py = y + r as u32 * FONT_SCALE + dy;
+
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Bilinearly interpolate the per vertex values returned by `f` at the given grid position.
+fn sample(terrain: &Terrain, x: f32, y: f32, f: impl Fn(usize, usize) -> f32) -> f32 {
+    let x0 = (x.floor() as usize).min(terrain.width() - 1);
+    let y0 = (y.floor() as usize).min(terrain.depth() - 1);
+    let x1 = (x0 + 1).min(terrain.width() - 1);
+    let y1 = (y0 + 1).min(terrain.depth() - 1);
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let top = f(x0, y0) * (1.0 - tx) + f(x1, y0) * tx;
+    let bottom = f(x0, y1) * (1.0 - tx) + f(x1, y1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
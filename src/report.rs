@@ -0,0 +1,72 @@
+use std::path::Path;
+use std::time::Instant;
+
+use serde_json::json;
+
+use crate::Terrain;
+
+/// Prints a JSON object per generated file to stdout, one per line, when enabled.
+#[derive(Debug)]
+pub struct Reporter {
+    enabled: bool,
+    start: Instant,
+}
+
+impl Reporter {
+    pub fn new(enabled: bool) -> Self {
+        Reporter {
+            enabled,
+            start: Instant::now(),
+        }
+    }
+
+    /// Report a file that contains the given terrain, either as a mesh or as raw heights.
+    pub fn terrain(
+        &self,
+        path: &Path,
+        format: &str,
+        terrain: &Terrain,
+        vertices: usize,
+        faces: usize,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let (min_height, max_height) = terrain
+            .iter_by_depth()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (_, _, z)| {
+                (lo.min(z), hi.max(z))
+            });
+
+        println!(
+            "{}",
+            json!({
+                "path": path.to_string_lossy(),
+                "format": format,
+                "seed": terrain.generator().seed(),
+                "vertices": vertices,
+                "faces": faces,
+                "min_height": min_height,
+                "max_height": max_height,
+                "duration": self.start.elapsed().as_secs_f64(),
+            })
+        );
+    }
+
+    /// Report an auxiliary file, like an image or a sidecar.
+    pub fn file(&self, path: &Path, format: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        println!(
+            "{}",
+            json!({
+                "path": path.to_string_lossy(),
+                "format": format,
+                "duration": self.start.elapsed().as_secs_f64(),
+            })
+        );
+    }
+}
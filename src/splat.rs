@@ -0,0 +1,99 @@
+use clap::{Parser, ValueEnum};
+use image::{Rgba, RgbaImage};
+
+use crate::Terrain;
+
+#[derive(Parser)]
+pub struct SplatConfig {
+    /// Bake a PNG texture where each pixel is classified as sand, grass, rock or snow based on
+    /// the altitude and the slope of the terrain.
+    #[arg(long = "bake-splat")]
+    pub bake_splat: Option<std::path::PathBuf>,
+
+    /// How the zones are encoded in the baked splat texture.
+    #[arg(long = "splat-style", value_enum, default_value = "color")]
+    pub style: SplatStyle,
+
+    /// Relative altitude in [0, 1] below which the terrain is considered sand.
+    #[arg(long = "sand-altitude", default_value = "0.15")]
+    pub sand_altitude: f32,
+
+    /// Relative altitude in [0, 1] above which the terrain is considered snow.
+    #[arg(long = "snow-altitude", default_value = "0.8")]
+    pub snow_altitude: f32,
+
+    /// Slope in degrees above which the terrain is considered rock regardless of its altitude.
+    #[arg(long = "rock-slope", default_value = "40")]
+    pub rock_slope: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplatStyle {
+    /// Paint each zone with a representative color.
+    Color,
+
+    /// Store each zone in its own channel: sand in red, grass in green, rock in blue and snow in
+    /// alpha, as expected by most game engines.
+    Channels,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Sand,
+    Grass,
+    Rock,
+    Snow,
+}
+
+impl SplatConfig {
+    pub fn classify(&self, altitude: f32, slope: f32) -> Zone {
+        if slope >= self.rock_slope {
+            Zone::Rock
+        } else if altitude >= self.snow_altitude {
+            Zone::Snow
+        } else if altitude <= self.sand_altitude {
+            Zone::Sand
+        } else {
+            Zone::Grass
+        }
+    }
+}
+
+impl Zone {
+    pub fn pixel(self, style: SplatStyle) -> Rgba<u8> {
+        match (style, self) {
+            (SplatStyle::Color, Zone::Sand) => Rgba([219, 202, 147, 255]),
+            (SplatStyle::Color, Zone::Grass) => Rgba([96, 140, 62, 255]),
+            (SplatStyle::Color, Zone::Rock) => Rgba([120, 110, 100, 255]),
+            (SplatStyle::Color, Zone::Snow) => Rgba([245, 245, 250, 255]),
+            (SplatStyle::Channels, Zone::Sand) => Rgba([255, 0, 0, 0]),
+            (SplatStyle::Channels, Zone::Grass) => Rgba([0, 255, 0, 0]),
+            (SplatStyle::Channels, Zone::Rock) => Rgba([0, 0, 255, 0]),
+            (SplatStyle::Channels, Zone::Snow) => Rgba([0, 0, 0, 255]),
+        }
+    }
+}
+
+/// Bake the splat texture of the given terrain. The image has one pixel per vertex and its rows
+/// go from the back of the terrain to the front, like the heightmaps it can be generated from.
+pub fn bake_splat(terrain: &Terrain, cfg: &SplatConfig) -> RgbaImage {
+    let (min_z, max_z) = terrain
+        .iter_by_depth()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (_, _, z)| {
+            (lo.min(z), hi.max(z))
+        });
+    let range = (max_z - min_z).max(f32::EPSILON);
+
+    let width = terrain.width() as u32;
+    let depth = terrain.depth() as u32;
+
+    RgbaImage::from_fn(width, depth, |px, py| {
+        let x = px as usize;
+        let y = terrain.depth() - 1 - py as usize;
+
+        let altitude = (terrain.height_at(x, y) - min_z) / range;
+        let slope = terrain.slope_at(x, y).atan().to_degrees();
+
+        cfg.classify(altitude, slope).pixel(cfg.style)
+    })
+}
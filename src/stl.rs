@@ -0,0 +1,58 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::Write;
+
+use crate::mesh::Mesh;
+
+/// Write the mesh as a binary [STL][0] file, splitting every face into triangles. Materials are
+/// not supported by the format and are ignored.
+///
+/// [0]: https://en.wikipedia.org/wiki/STL_(file_format)#Binary
+pub fn dump_stl(w: &mut impl Write, mesh: &Mesh) -> io::Result<()> {
+    let triangles = mesh.triangles().collect::<Vec<_>>();
+    let count = u32::try_from(triangles.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many triangles for stl"))?;
+
+    // the header is free form, but it must not start with "solid" otherwise some readers think
+    // the file is ascii
+    let mut header = [b' '; 80];
+    let title = b"terrain-mesh";
+    header[..title.len()].copy_from_slice(title);
+
+    w.write_all(&header)?;
+    w.write_all(&count.to_le_bytes())?;
+
+    for [a, b, c] in triangles {
+        let (a, b, c) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+
+        for v in &[normal(a, b, c), a, b, c] {
+            for coord in v {
+                w.write_all(&coord.to_le_bytes())?;
+            }
+        }
+
+        // attribute byte count
+        w.write_all(&[0, 0])?;
+    }
+
+    Ok(())
+}
+
+/// The unit normal of the counter clockwise triangle, or zero if it's degenerate.
+fn normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len <= f32::EPSILON {
+        return [0.0; 3];
+    }
+
+    [n[0] / len, n[1] / len, n[2] / len]
+}
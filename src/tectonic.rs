@@ -0,0 +1,203 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+use simdnoise::NoiseBuilder;
+
+use clap::Parser;
+
+use crate::grid::{blur, erode, normalize};
+
+/// How much the heights change per unit of convergence at every step.
+const UPLIFT_RATE: f32 = 0.5;
+
+/// The height above which collisions can't raise the crust anymore.
+const MAX_HEIGHT: f32 = 1.0;
+
+#[derive(Parser)]
+pub struct TectonicConfig {
+    /// The width of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub width: u16,
+
+    /// The depth of the final terrain as in number of vertices.
+    #[arg(short, long, default_value = "101")]
+    pub depth: u16,
+
+    /// The seed to use to generate the terrain. You can find the seed of a given terrain by
+    /// inspecting the obj file.
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// The number of tectonic plates.
+    #[arg(short, long, default_value = "6")]
+    pub plates: usize,
+
+    /// The number of simulation steps, more steps lead to taller and wider mountain belts.
+    #[arg(long, default_value = "40")]
+    pub steps: usize,
+
+    /// The number of thermal erosion iterations run after every step.
+    #[arg(long, default_value = "4")]
+    pub erosion: usize,
+
+    /// The maximum height of the terrain. If `base-thickness` is specified then the final mesh has
+    /// a potential maximum height of `base-thickness` + `amplitude`.
+    #[arg(short, long, default_value = "20")]
+    pub amplitude: f32,
+
+    /// The thickness of the base upon which the terrain is generated.
+    #[arg(long = "base-thickness", default_value = "0.0")]
+    pub base_thickness: f32,
+}
+
+#[derive(Debug)]
+struct Plate {
+    center: (f32, f32),
+    velocity: (f32, f32),
+    continental: bool,
+}
+
+/// Simulate a handful of plates that drift over the domain, raising mountain belts where they
+/// collide and rifts where they pull apart, and return the resulting heights normalized in
+/// [0, 1] in row major order.
+///
+/// This is a very coarse approximation: plates are Voronoi cells around moving centers whose
+/// boundaries are perturbed by noise, and the crust doesn't move along with them.
+pub fn simulate(cfg: &TectonicConfig, seed: u64) -> Vec<f32> {
+    let width = usize::from(cfg.width);
+    let depth = usize::from(cfg.depth);
+    let mut rng = Pcg32::seed_from_u64(seed);
+
+    let mut plates = (0..cfg.plates.max(2))
+        .map(|i| {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = rng.gen_range(0.2..1.0);
+
+            Plate {
+                center: (
+                    rng.gen_range(0.0..width as f32),
+                    rng.gen_range(0.0..depth as f32),
+                ),
+                velocity: (angle.cos() * speed, angle.sin() * speed),
+                continental: i == 0 || rng.gen_bool(0.5),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let jitter = width.max(depth) as f32 * 0.1;
+    let mut jitter_noise = || {
+        NoiseBuilder::fbm_2d(width, depth)
+            .with_freq(0.05)
+            .with_octaves(3)
+            .with_seed(rng.gen())
+            .generate_scaled(-jitter, jitter)
+    };
+    let (jitter_x, jitter_y) = (jitter_noise(), jitter_noise());
+
+    let assign = |plates: &[Plate]| {
+        (0..width * depth)
+            .map(|i| {
+                let x = (i % width) as f32 + jitter_x[i];
+                let y = (i / width) as f32 + jitter_y[i];
+
+                (0..plates.len())
+                    .min_by(|&a, &b| {
+                        let da = (plates[a].center.0 - x).hypot(plates[a].center.1 - y);
+                        let db = (plates[b].center.0 - x).hypot(plates[b].center.1 - y);
+                        da.partial_cmp(&db).unwrap()
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let spread = width.max(depth) as f32 / 40.0;
+
+    // the continental shelves slope gently towards the ocean floor
+    let crust = assign(&plates)
+        .iter()
+        .map(|&p| if plates[p].continental { 0.3 } else { 0.0 })
+        .collect::<Vec<_>>();
+    let mut heights = blur(crust, width, depth, spread * 2.0);
+
+    // how much every cell has been raised, used to add ridges to the mountain belts
+    let mut orogeny = vec![0.0; width * depth];
+
+    for _ in 0..cfg.steps {
+        let owners = assign(&plates);
+
+        let mut uplift = vec![0.0; width * depth];
+        for i in 0..width * depth {
+            let (x, y) = (i % width, i / width);
+            let a = &plates[owners[i]];
+
+            let neighbors = [(1, 0), (0, 1)]
+                .iter()
+                .filter(|&&(dx, dy)| x + dx < width && y + dy < depth)
+                .map(|&(dx, dy)| ((dx as f32, dy as f32), i + dy * width + dx));
+
+            for (n, j) in neighbors {
+                if owners[i] == owners[j] {
+                    continue;
+                }
+
+                let b = &plates[owners[j]];
+                let convergence =
+                    -((b.velocity.0 - a.velocity.0) * n.0 + (b.velocity.1 - a.velocity.1) * n.1);
+
+                // oceanic crust subducts under continental crust, raising a volcanic arc on the
+                // continental side and digging a trench on the other one
+                let (ua, ub) = match (a.continental, b.continental) {
+                    _ if convergence < 0.0 => (0.5, 0.5),
+                    (true, true) => (1.0, 1.0),
+                    (true, false) => (0.8, -0.4),
+                    (false, true) => (-0.4, 0.8),
+                    (false, false) => (0.3, 0.3),
+                };
+
+                uplift[i] += convergence * ua;
+                uplift[j] += convergence * ub;
+            }
+        }
+
+        let uplift = blur(uplift, width, depth, spread);
+
+        // isostasy stops the crust from growing indefinitely
+        for ((h, o), u) in heights.iter_mut().zip(&mut orogeny).zip(uplift) {
+            let u = u * UPLIFT_RATE;
+            if u > 0.0 {
+                *h += u * (MAX_HEIGHT - *h).max(0.0);
+                *o += u;
+            } else {
+                *h = (*h + u).max(-0.5);
+            }
+        }
+
+        // coarse erosion
+        for _ in 0..cfg.erosion {
+            erode(&mut heights, width, depth);
+        }
+
+        for plate in &mut plates {
+            plate.center.0 += plate.velocity.0;
+            plate.center.1 += plate.velocity.1;
+        }
+    }
+
+    let detail = NoiseBuilder::fbm_2d(width, depth)
+        .with_freq(0.1)
+        .with_octaves(4)
+        .with_seed(rng.gen())
+        .generate_scaled(-0.03, 0.03);
+    let ridges = NoiseBuilder::ridge_2d(width, depth)
+        .with_freq(0.08)
+        .with_octaves(5)
+        .with_seed(rng.gen())
+        .generate_scaled(0.0, 1.0);
+    let max_orogeny = orogeny.iter().fold(f32::EPSILON, |a, &b| a.max(b));
+    for (((h, d), r), o) in heights.iter_mut().zip(detail).zip(ridges).zip(orogeny) {
+        *h += d + (r - 0.5) * o / max_orogeny * 0.4;
+    }
+
+    normalize(&mut heights);
+    heights
+}
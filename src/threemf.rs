@@ -0,0 +1,177 @@
+use std::io;
+use std::io::{Seek, Write};
+
+use clap::{Parser, ValueEnum};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::mesh::{self, Coord, Material, Mesh};
+
+#[derive(Parser)]
+pub struct ThreeMfConfig {
+    /// The unit of one grid cell in the 3mf output, which makes slicers import the model at the
+    /// intended size instead of guessing it.
+    #[arg(long, value_enum, default_value = "millimeter")]
+    pub unit: Unit,
+}
+
+/// The units supported by 3mf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Unit {
+    Micron,
+    Millimeter,
+    Centimeter,
+    Inch,
+    Foot,
+    Meter,
+}
+
+impl Unit {
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Micron => "micron",
+            Unit::Millimeter => "millimeter",
+            Unit::Centimeter => "centimeter",
+            Unit::Inch => "inch",
+            Unit::Foot => "foot",
+            Unit::Meter => "meter",
+        }
+    }
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+/// Write the mesh as a [3mf][0] package containing a single object made of triangles. The
+/// materials, if any, are written as base materials. The coordinates are written with the given
+/// precision, see `Coord`.
+///
+/// [0]: https://3mf.io/specification/
+pub fn dump_3mf(
+    w: impl Write + Seek,
+    mesh: &Mesh,
+    cfg: &ThreeMfConfig,
+    precision: Option<u8>,
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(w);
+
+    zip.start_file("[Content_Types].xml", FileOptions::default())?;
+    zip.write_all(CONTENT_TYPES.as_bytes())?;
+
+    zip.start_file("_rels/.rels", FileOptions::default())?;
+    zip.write_all(RELATIONSHIPS.as_bytes())?;
+
+    zip.start_file("3D/3dmodel.model", FileOptions::default())?;
+    dump_model(&mut zip, mesh, cfg.unit, precision)?;
+
+    zip.finish()?.flush()
+}
+
+fn dump_model(
+    w: &mut impl Write,
+    mesh: &Mesh,
+    unit: Unit,
+    precision: Option<u8>,
+) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<model unit="{}" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">"#,
+        unit.name()
+    )?;
+    writeln!(
+        w,
+        r#"  <metadata name="Application">terrain-mesh</metadata>"#
+    )?;
+    if !mesh.comments.is_empty() {
+        writeln!(
+            w,
+            r#"  <metadata name="Description">{}</metadata>"#,
+            escape(&mesh.comments.join("\n"))
+        )?;
+    }
+
+    writeln!(w, "  <resources>")?;
+
+    let materials = mesh.has_materials();
+    if materials {
+        writeln!(w, r#"    <basematerials id="1">"#)?;
+        for m in &Material::ALL {
+            let [r, g, b] = m.color();
+            writeln!(
+                w,
+                r##"      <base name="{}" displaycolor="#{:02X}{:02X}{:02X}"/>"##,
+                m.name(),
+                (r * 255.0).round() as u8,
+                (g * 255.0).round() as u8,
+                (b * 255.0).round() as u8,
+            )?;
+        }
+        writeln!(w, "    </basematerials>")?;
+        writeln!(w, r#"    <object id="2" type="model" pid="1" pindex="0">"#)?;
+    } else {
+        writeln!(w, r#"    <object id="2" type="model">"#)?;
+    }
+
+    writeln!(w, "      <mesh>")?;
+    writeln!(w, "        <vertices>")?;
+    for &[x, y, z] in &mesh.vertices {
+        writeln!(
+            w,
+            r#"          <vertex x="{}" y="{}" z="{}"/>"#,
+            Coord(x, precision),
+            Coord(y, precision),
+            Coord(z, precision)
+        )?;
+    }
+    writeln!(w, "        </vertices>")?;
+
+    writeln!(w, "        <triangles>")?;
+    for face in &mesh.faces {
+        let material = face
+            .material
+            .filter(|_| materials)
+            .map(|m| Material::ALL.iter().position(|&o| o == m).unwrap());
+
+        for [a, b, c] in mesh::fan(&face.vertices) {
+            match material {
+                Some(p) => writeln!(
+                    w,
+                    r#"          <triangle v1="{}" v2="{}" v3="{}" pid="1" p1="{}"/>"#,
+                    a, b, c, p
+                )?,
+                None => writeln!(
+                    w,
+                    r#"          <triangle v1="{}" v2="{}" v3="{}"/>"#,
+                    a, b, c
+                )?,
+            }
+        }
+    }
+    writeln!(w, "        </triangles>")?;
+    writeln!(w, "      </mesh>")?;
+    writeln!(w, "    </object>")?;
+    writeln!(w, "  </resources>")?;
+
+    writeln!(w, "  <build>")?;
+    writeln!(w, r#"    <item objectid="2"/>"#)?;
+    writeln!(w, "  </build>")?;
+    writeln!(w, "</model>")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
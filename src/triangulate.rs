@@ -0,0 +1,156 @@
+/// A point on the plane.
+pub type Point = (f32, f32);
+
+/// Triangulate the polygon bounded by the `outer` ring with the given `holes` via ear clipping.
+///
+/// The rings can have any orientation and must not intersect each other. The returned triangles
+/// are counter clockwise and index into the concatenation of `outer` and all the `holes` in
+/// order.
+pub fn triangulate(outer: &[Point], holes: &[Vec<Point>]) -> Vec<[usize; 3]> {
+    let mut points = outer.to_vec();
+    let mut poly = oriented((0..outer.len()).collect(), &points, true);
+
+    let mut hole_rings = vec![];
+    for hole in holes {
+        let start = points.len();
+        points.extend_from_slice(hole);
+        hole_rings.push(oriented((start..points.len()).collect(), &points, false));
+    }
+
+    // holes must be bridged from the rightmost one for the bridges not to cross each other
+    hole_rings.sort_by(|a, b| max_x(b, &points).partial_cmp(&max_x(a, &points)).unwrap());
+    for hole in hole_rings {
+        bridge_hole(&mut poly, &hole, &points);
+    }
+
+    clip_ears(poly, &points)
+}
+
+fn oriented(mut ring: Vec<usize>, points: &[Point], ccw: bool) -> Vec<usize> {
+    if (signed_area(&ring, points) > 0.0) != ccw {
+        ring.reverse();
+    }
+    ring
+}
+
+fn signed_area(ring: &[usize], points: &[Point]) -> f32 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(&a, &b)| points[a].0 * points[b].1 - points[b].0 * points[a].1)
+        .sum::<f32>()
+        / 2.0
+}
+
+fn max_x(ring: &[usize], points: &[Point]) -> f32 {
+    ring.iter()
+        .map(|&i| points[i].0)
+        .fold(f32::NEG_INFINITY, f32::max)
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+/// Connect the hole to the polygon with a zero width channel from the rightmost vertex of the
+/// hole to a vertex of the polygon that is visible from it.
+fn bridge_hole(poly: &mut Vec<usize>, hole: &[usize], points: &[Point]) {
+    let (hi, &m) = hole
+        .iter()
+        .enumerate()
+        .max_by(|(_, &a), (_, &b)| points[a].0.partial_cmp(&points[b].0).unwrap())
+        .unwrap();
+    let mp = points[m];
+
+    // cast a ray towards +x and find the closest edge it hits
+    let mut best: Option<(f32, usize)> = None;
+    for i in 0..poly.len() {
+        let a = points[poly[i]];
+        let b = points[poly[(i + 1) % poly.len()]];
+
+        if (a.1 > mp.1) == (b.1 > mp.1) {
+            continue;
+        }
+
+        let x = a.0 + (mp.1 - a.1) / (b.1 - a.1) * (b.0 - a.0);
+        if x >= mp.0 && best.is_none_or(|(bx, _)| x < bx) {
+            let p = if a.0 > b.0 { i } else { (i + 1) % poly.len() };
+            best = Some((x, p));
+        }
+    }
+
+    let (ix, mut p) = match best {
+        Some(best) => best,
+        None => return,
+    };
+
+    // another vertex inside the triangle formed by the ray might block the view, in that case
+    // pick the one with the smallest angle with the ray
+    let candidate = points[poly[p]];
+    let hit = (ix, mp.1);
+    let mut best_angle = f32::INFINITY;
+    for (i, &vi) in poly.iter().enumerate() {
+        let v = points[vi];
+        if vi == poly[p] || v.0 < mp.0 {
+            continue;
+        }
+
+        let inside = if candidate.1 < mp.1 {
+            in_triangle(v, mp, candidate, hit)
+        } else {
+            in_triangle(v, mp, hit, candidate)
+        };
+        if !inside {
+            continue;
+        }
+
+        let angle = (v.1 - mp.1).abs().atan2(v.0 - mp.0);
+        if angle < best_angle {
+            best_angle = angle;
+            p = i;
+        }
+    }
+
+    let mut bridged = Vec::with_capacity(poly.len() + hole.len() + 2);
+    bridged.extend_from_slice(&poly[..=p]);
+    bridged.extend(hole[hi..].iter().chain(&hole[..=hi]));
+    bridged.extend_from_slice(&poly[p..]);
+    *poly = bridged;
+}
+
+fn clip_ears(mut poly: Vec<usize>, points: &[Point]) -> Vec<[usize; 3]> {
+    let mut triangles = vec![];
+
+    while poly.len() > 3 {
+        let n = poly.len();
+
+        let is_ear = |i: usize| {
+            let (a, b, c) = (poly[(i + n - 1) % n], poly[i], poly[(i + 1) % n]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+
+            if cross(pa, pb, pc) <= 0.0 {
+                return false;
+            }
+
+            poly.iter().all(|&v| {
+                let pv = points[v];
+                pv == pa || pv == pb || pv == pc || !in_triangle(pv, pa, pb, pc)
+            })
+        };
+
+        // degenerate inputs might not have any ear left, clip anyway to always make progress
+        let i = (0..n).find(|&i| is_ear(i)).unwrap_or(0);
+
+        triangles.push([poly[(i + n - 1) % n], poly[i], poly[(i + 1) % n]]);
+        poly.remove(i);
+    }
+
+    if poly.len() == 3 {
+        triangles.push([poly[0], poly[1], poly[2]]);
+    }
+
+    triangles
+}
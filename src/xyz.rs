@@ -0,0 +1,220 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::grid::Raster;
+
+/// How the scattered samples are turned into the heights of the lattice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Regrid {
+    /// Every vertex takes the height of the closest sample within `SEARCH_RADIUS` cells.
+    Nearest,
+
+    /// Every sample is spread over the 4 surrounding vertices with bilinear weights and the
+    /// vertices take the weighted average of the samples that reached them.
+    Bilinear,
+}
+
+/// The maximum number of vertices of the lattice, to catch spacings that are too small for the
+/// extent of the samples.
+const MAX_VERTICES: usize = 64 * 1024 * 1024;
+
+/// How far from a vertex, in cells, the nearest sample is looked for.
+const SEARCH_RADIUS: f64 = 2.0;
+
+/// Read a text file of `x,y,z` samples, one per line, and regrid them onto a lattice with the
+/// given spacing, in the same units as the coordinates. The values can be separated by commas,
+/// semicolons or whitespace; empty lines, comments starting with `#` and lines that don't start
+/// with a number, like a CSV header, are skipped.
+///
+/// The spacing defaults to the distance between the columns of gridded samples, and to the one
+/// that gives a lattice with about as many vertices as samples for scattered ones. The vertices
+/// that no sample reaches are missing.
+pub fn read_xyz(path: &Path, spacing: Option<f64>, regrid: Regrid) -> io::Result<Raster> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let text = fs::read_to_string(path)?;
+
+    let mut points = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut values = line
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .map(str::parse::<f64>);
+
+        match (values.next(), values.next(), values.next()) {
+            (Some(Ok(x)), Some(Ok(y)), Some(Ok(z))) => points.push([x, y, z]),
+            // a header
+            (Some(Err(_)), _, _) if points.is_empty() => continue,
+            _ => {
+                return Err(invalid(format!(
+                    "{}:{} is not a valid x,y,z sample",
+                    path.display(),
+                    i + 1
+                )))
+            }
+        }
+    }
+
+    if points.is_empty() {
+        return Err(invalid(format!("{} has no samples", path.display())));
+    }
+
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, max_x, min_y, max_y), &[x, y, _]| {
+            (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+        },
+    );
+
+    let spacing = match spacing {
+        Some(s) => s,
+        None => {
+            let distinct = |axis: usize| {
+                let mut values = points.iter().map(|p| p[axis]).collect::<Vec<_>>();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                values.dedup();
+                values
+            };
+            let (xs, ys) = (distinct(0), distinct(1));
+
+            if xs.len().saturating_mul(ys.len()) <= 2 * points.len() {
+                // the samples are on a grid, possibly with some holes, whose columns are the
+                // spacing apart
+                xs.windows(2)
+                    .map(|w| w[1] - w[0])
+                    .fold(f64::INFINITY, f64::min)
+            } else {
+                // solve (w / s + 1) * (h / s + 1) = n for s
+                let (w, h) = (max_x - min_x, max_y - min_y);
+                let n = (points.len() - 1) as f64;
+                ((w + h) + ((w + h).powi(2) + 4.0 * n * w * h).sqrt()) / (2.0 * n)
+            }
+        }
+    };
+    if !spacing.is_finite() || spacing <= 0.0 {
+        return Err(invalid(format!(
+            "cannot infer the spacing of the samples of {}, pass it explicitly",
+            path.display()
+        )));
+    }
+
+    let width = ((max_x - min_x) / spacing).round() as usize + 1;
+    let depth = ((max_y - min_y) / spacing).round() as usize + 1;
+    if width < 2 || depth < 2 {
+        return Err(invalid(format!(
+            "the samples of {} span less than 2 vertices along one of the axes",
+            path.display()
+        )));
+    }
+    if width.saturating_mul(depth) > MAX_VERTICES {
+        return Err(invalid(format!(
+            "a spacing of {} gives a {}x{} lattice, pass a bigger one",
+            spacing, width, depth
+        )));
+    }
+
+    // the rows go from the top left corner, that is from the highest y
+    let lattice = |x: f64, y: f64| ((x - min_x) / spacing, (max_y - y) / spacing);
+
+    let samples = match regrid {
+        Regrid::Nearest => {
+            // bucket the samples by their closest vertex so that every vertex only looks at the
+            // samples around it
+            let mut sorted = points
+                .iter()
+                .map(|&[x, y, z]| {
+                    let (c, r) = lattice(x, y);
+                    (r.round() as usize * width + c.round() as usize, c, r, z)
+                })
+                .collect::<Vec<_>>();
+            sorted.sort_by_key(|s| s.0);
+
+            let mut starts = vec![0; width * depth + 1];
+            for s in &sorted {
+                starts[s.0 + 1] += 1;
+            }
+            for i in 0..width * depth {
+                starts[i + 1] += starts[i];
+            }
+
+            let reach = SEARCH_RADIUS.ceil() as usize;
+            (0..width * depth)
+                .map(|i| {
+                    let (col, row) = (i % width, i / width);
+                    let mut nearest: Option<(f64, f64)> = None;
+
+                    for r in row.saturating_sub(reach)..=(row + reach).min(depth - 1) {
+                        for c in col.saturating_sub(reach)..=(col + reach).min(width - 1) {
+                            let bucket = r * width + c;
+                            for &(_, sc, sr, z) in &sorted[starts[bucket]..starts[bucket + 1]] {
+                                let dist = (sc - col as f64).powi(2) + (sr - row as f64).powi(2);
+                                if dist <= SEARCH_RADIUS.powi(2)
+                                    && nearest.is_none_or(|(d, _)| dist < d)
+                                {
+                                    nearest = Some((dist, z));
+                                }
+                            }
+                        }
+                    }
+
+                    nearest.map(|(_, z)| z as f32)
+                })
+                .collect()
+        }
+        Regrid::Bilinear => {
+            let mut sums = vec![(0.0, 0.0); width * depth];
+
+            for &[x, y, z] in &points {
+                // the extent might not be a multiple of the spacing, keep the samples on the
+                // last vertices instead of extrapolating
+                let (c, r) = lattice(x, y);
+                let (c, r) = (c.min((width - 1) as f64), r.min((depth - 1) as f64));
+                let (c0, r0) = (
+                    (c.floor() as usize).min(width - 2),
+                    (r.floor() as usize).min(depth - 2),
+                );
+                let (tc, tr) = (c - c0 as f64, r - r0 as f64);
+
+                for &(row, col, w) in &[
+                    (r0, c0, (1.0 - tc) * (1.0 - tr)),
+                    (r0, c0 + 1, tc * (1.0 - tr)),
+                    (r0 + 1, c0, (1.0 - tc) * tr),
+                    (r0 + 1, c0 + 1, tc * tr),
+                ] {
+                    let (wz, ws) = &mut sums[row * width + col];
+                    *wz += w * z;
+                    *ws += w;
+                }
+            }
+
+            sums.into_iter()
+                .map(|(wz, ws)| {
+                    if ws > f64::EPSILON {
+                        Some((wz / ws) as f32)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        }
+    };
+
+    Ok(Raster {
+        width,
+        depth,
+        samples,
+    })
+}